@@ -0,0 +1,184 @@
+//! Saves the culling progress in the background shortly after every change, so a crash or a
+//! power cut costs a few seconds of culling instead of the whole session.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    file_operations::{save_culling_progress, save_history},
+    history::History,
+    models::ImageInfo,
+    storage::FolderSettings,
+    xmp::{self, SidecarRating},
+    BlitzApp,
+};
+
+/// Rating a burst means a keypress every second or so, only the state after it gets written.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Someone culling without a pause still gets a save this often.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// A snapshot of everything that is saved into the `.blitz` folder.
+pub struct SaveRequest {
+    pub photo_dir: PathBuf,
+    pub photos: Vec<ImageInfo>,
+    pub settings: FolderSettings,
+    pub history: History,
+    /// Photos whose sidecars are behind, empty unless sidecars are written.
+    pub sidecars: Vec<ImageInfo>,
+}
+
+struct Job {
+    request: SaveRequest,
+    /// Set for saves that can't wait, told once the request is written.
+    written: Option<mpsc::Sender<()>>,
+}
+
+/// The only writer of the `.blitz` folder once it's started, so saves never run into each
+/// other and an older snapshot never overwrites a newer one.
+pub struct Autosaver {
+    sender: mpsc::Sender<Job>,
+}
+
+impl Autosaver {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("autosave".to_string())
+            .spawn(move || run(receiver))
+            .expect("couldn't start the autosave thread");
+        Self { sender }
+    }
+
+    pub fn request(&self, request: SaveRequest) {
+        let _ = self.sender.send(Job {
+            request,
+            written: None,
+        });
+    }
+
+    /// Writes `request` without waiting for more changes and returns once it's on disk. It
+    /// replaces the autosave pending before it.
+    pub fn save_now(&self, request: SaveRequest) {
+        let (written, done) = mpsc::channel();
+        let job = Job {
+            request,
+            written: Some(written),
+        };
+        if self.sender.send(job).is_ok() {
+            let _ = done.recv();
+        }
+    }
+}
+
+fn run(receiver: Receiver<Job>) {
+    while let Ok(mut job) = receiver.recv() {
+        let first_change = Instant::now();
+        // Unlike the snapshot, sidecar changes add up until they are written
+        let mut sidecars = HashMap::new();
+        loop {
+            sidecars.extend(
+                job.request
+                    .sidecars
+                    .drain(..)
+                    .map(|photo| (photo.path_processed.clone(), photo)),
+            );
+            if job.written.is_some() {
+                break;
+            }
+            let wait = DEBOUNCE.min(MAX_DELAY.saturating_sub(first_change.elapsed()));
+            match receiver.recv_timeout(wait) {
+                Ok(newer_job) => job = newer_job,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        save(&job.request, sidecars.values());
+        if let Some(written) = job.written {
+            let _ = written.send(());
+        }
+    }
+}
+
+fn save<'a>(request: &SaveRequest, sidecars: impl Iterator<Item = &'a ImageInfo>) {
+    if let Err(err) = save_culling_progress(&request.photo_dir, &request.photos, &request.settings)
+    {
+        log::warn!("Autosave of {:?} failed: {}", request.photo_dir, err);
+    }
+    if let Err(err) = save_history(&request.photo_dir, &request.history) {
+        log::warn!("Autosave of the history failed: {}", err);
+    }
+    for photo in sidecars {
+        if let Err(err) = xmp::write_photo_sidecars(photo) {
+            log::warn!(
+                "Couldn't write the sidecar of {:?}: {}",
+                photo.path_processed,
+                err
+            );
+        }
+    }
+}
+
+impl BlitzApp {
+    /// Hands a snapshot to the autosave thread whenever the history moved since the last one.
+    pub fn autosave_if_changed(&mut self) {
+        let revision = self.history.revision();
+        if revision == self.autosaved_revision {
+            return;
+        }
+        let Some(photos) = self.photos.try_read().ok().map(|photos| photos.clone()) else {
+            // Busy, the next frame tries again
+            return;
+        };
+        self.autosaved_revision = revision;
+        if photos.is_empty() {
+            return;
+        }
+
+        let request = self.save_request(photos);
+        self.autosaver
+            .get_or_insert_with(Autosaver::spawn)
+            .request(request);
+    }
+
+    /// Writes `photos` and the history right away instead of waiting for the autosave, for
+    /// when the files are about to be rescanned or the app closes.
+    pub fn save_now(&mut self, photos: Vec<ImageInfo>) {
+        if photos.is_empty() {
+            return;
+        }
+        self.autosaved_revision = self.history.revision();
+        let request = self.save_request(photos);
+        self.autosaver
+            .get_or_insert_with(Autosaver::spawn)
+            .save_now(request);
+    }
+
+    fn save_request(&mut self, photos: Vec<ImageInfo>) -> SaveRequest {
+        let mut sidecars = Vec::new();
+        if self.write_xmp_sidecars {
+            for photo in photos.iter() {
+                let rating = SidecarRating::of(photo);
+                if self.sidecar_ratings.get(&photo.path_processed) != Some(&rating) {
+                    self.sidecar_ratings
+                        .insert(photo.path_processed.clone(), rating);
+                    sidecars.push(photo.clone());
+                }
+            }
+        }
+
+        SaveRequest {
+            photo_dir: self.photo_dir.clone(),
+            photos,
+            settings: self.folder_settings(),
+            history: self.history.clone(),
+            sidecars,
+        }
+    }
+}
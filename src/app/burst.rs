@@ -0,0 +1,125 @@
+//! Groups frames shot in quick succession so they can be culled as one stack.
+
+use std::collections::HashMap;
+
+use super::models::ImageInfo;
+
+/// Frames further apart than this are separate shots, even at slow burst rates.
+pub const BURST_MAX_GAP_MILLIS: i64 = 1_000;
+
+/// Gives every run of two or more consecutive frames of the same folder a shared `burst_id`.
+/// Frames belong together when their capture times are close and, if both file names carry
+/// one, their sequence numbers follow each other.
+pub fn assign_bursts(photos: &mut [ImageInfo]) {
+    let mut next_burst_id = 0;
+    let mut run_start = 0;
+
+    for index in 0..=photos.len() {
+        let continues_run =
+            index > 0 && index < photos.len() && is_next_frame(&photos[index - 1], &photos[index]);
+        if continues_run {
+            continue;
+        }
+
+        let run = &mut photos[run_start..index];
+        let burst_id = (run.len() > 1).then_some(next_burst_id);
+        for photo in run.iter_mut() {
+            photo.burst_id = burst_id;
+        }
+        if burst_id.is_some() {
+            next_burst_id += 1;
+        }
+        run_start = index;
+    }
+}
+
+/// Photo indices per burst, in queue order.
+pub fn burst_members(photos: &[ImageInfo]) -> HashMap<usize, Vec<usize>> {
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, photo) in photos.iter().enumerate() {
+        if let Some(burst_id) = photo.burst_id {
+            members.entry(burst_id).or_default().push(index);
+        }
+    }
+    members
+}
+
+fn is_next_frame(previous: &ImageInfo, current: &ImageInfo) -> bool {
+    if previous.path_processed.parent() != current.path_processed.parent() {
+        return false;
+    }
+    let (Some(previous_time), Some(current_time)) = (
+        previous.metadata.capture_time,
+        current.metadata.capture_time,
+    ) else {
+        return false;
+    };
+    let gap = current_time.as_millis() - previous_time.as_millis();
+    if !(0..=BURST_MAX_GAP_MILLIS).contains(&gap) {
+        return false;
+    }
+
+    match (
+        sequence_number(&previous.image_name),
+        sequence_number(&current.image_name),
+    ) {
+        (Some(previous_number), Some(current_number)) => current_number == previous_number + 1,
+        _ => true,
+    }
+}
+
+/// The frame counter cameras put at the end of the file name, e.g. 1234 for `DSCF1234.JPG`.
+fn sequence_number(image_name: &str) -> Option<u64> {
+    let stem = image_name
+        .rsplit_once('.')
+        .map_or(image_name, |(stem, _)| stem);
+    let digits_start = stem
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |position| position + 1);
+    stem[digits_start..].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::exif::CaptureTime;
+    use std::path::PathBuf;
+
+    fn frame(name: &str, date_time: &str, sub_sec: &str) -> ImageInfo {
+        let mut photo = ImageInfo {
+            path_processed: PathBuf::from("/shoot").join(name),
+            image_name: name.to_string(),
+            ..Default::default()
+        };
+        photo.metadata.capture_time = CaptureTime::parse(date_time, Some(sub_sec));
+        photo
+    }
+
+    #[test]
+    fn test_assign_bursts() {
+        let mut photos = vec![
+            frame("DSCF0001.JPG", "2024:06:01 10:00:00", "00"),
+            frame("DSCF0002.JPG", "2024:06:01 10:00:00", "10"),
+            frame("DSCF0003.JPG", "2024:06:01 10:00:00", "20"),
+            frame("DSCF0004.JPG", "2024:06:01 10:00:05", "00"),
+            frame("DSCF0005.JPG", "2024:06:01 10:00:05", "50"),
+            // Same second but a frame was deleted in between
+            frame("DSCF0007.JPG", "2024:06:01 10:00:05", "90"),
+        ];
+        assign_bursts(&mut photos);
+
+        let burst_ids: Vec<Option<usize>> = photos.iter().map(|p| p.burst_id).collect();
+        assert_eq!(
+            vec![Some(0), Some(0), Some(0), Some(1), Some(1), None],
+            burst_ids
+        );
+        assert_eq!(Some(&vec![3, 4]), burst_members(&photos).get(&1));
+    }
+
+    #[test]
+    fn test_sequence_number() {
+        assert_eq!(Some(1234), sequence_number("DSCF1234.JPG"));
+        assert_eq!(Some(1), sequence_number("IMG_0001.jpg"));
+        assert_eq!(None, sequence_number("portrait.jpg"));
+    }
+}
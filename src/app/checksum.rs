@@ -0,0 +1,207 @@
+//! SHA-256 checksums of committed files. Verified commits hash every file before and after the
+//! transfer and record the wheat files in a `manifest.sha256`, in the format `sha256sum -c`
+//! understands, so the folder can be checked again later.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{commit_mode::CommitMode, storage};
+
+pub const MANIFEST_NAME: &str = "manifest.sha256";
+
+/// The hex encoded SHA-256 of the file.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = io::BufReader::new(fs::File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Transfers the file in `mode` and makes sure the destination hashes the same as the source
+/// did beforehand, returning the checksum. A file that doesn't match stays where it was.
+pub fn transfer_verified(
+    mode: CommitMode,
+    source: &Path,
+    destination: &Path,
+) -> io::Result<String> {
+    let checksum = sha256_file(source)?;
+    mode.transfer_checked(
+        source,
+        destination,
+        &|transferred| match sha256_file(transferred)? == checksum {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the checksum of the destination doesn't match",
+            )),
+        },
+    )?;
+    Ok(checksum)
+}
+
+/// The checksums of the files in a folder, keyed by their path relative to it.
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub checksums: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(MANIFEST_NAME)
+    }
+
+    /// Reads the manifest of `dir`, a folder without one has an empty manifest.
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let content = match fs::read_to_string(Self::path(dir)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        let checksums = content
+            .lines()
+            .filter_map(|line| {
+                let (checksum, relative_path) = line.split_once("  ")?;
+                Some((PathBuf::from(relative_path), checksum.to_string()))
+            })
+            .collect();
+        Ok(Self { checksums })
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let content: String = self
+            .checksums
+            .iter()
+            .map(|(relative_path, checksum)| {
+                let relative_path = relative_path.to_string_lossy().replace('\\', "/");
+                format!("{}  {}\n", checksum, relative_path)
+            })
+            .collect();
+        fs::create_dir_all(dir)?;
+        // Cut short, it would lose the checksums of every earlier commit
+        storage::write_atomically(&Self::path(dir), content.as_bytes())
+    }
+
+    /// Records `checksum` for `file`, which has to be inside `dir`.
+    pub fn insert(&mut self, dir: &Path, file: &Path, checksum: String) {
+        if let Ok(relative_path) = file.strip_prefix(dir) {
+            self.checksums.insert(relative_path.to_path_buf(), checksum);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn remove(&mut self, dir: &Path, file: &Path) {
+        if let Ok(relative_path) = file.strip_prefix(dir) {
+            self.checksums.remove(relative_path);
+        }
+    }
+}
+
+/// A file of the manifest that is missing or no longer matches its checksum.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestProblem {
+    pub path: PathBuf,
+    pub problem: String,
+}
+
+pub struct VerifyReport {
+    pub dir: PathBuf,
+    pub checked: usize,
+    pub problems: Vec<ManifestProblem>,
+}
+
+/// Hashes every file listed in the manifest of `dir` again.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn verify_manifest(dir: &Path) -> VerifyReport {
+    let mut report = VerifyReport {
+        dir: dir.to_path_buf(),
+        checked: 0,
+        problems: Vec::new(),
+    };
+    let manifest = match Manifest::load(dir) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            report.problems.push(ManifestProblem {
+                path: Manifest::path(dir),
+                problem: err.to_string(),
+            });
+            return report;
+        }
+    };
+
+    for (relative_path, checksum) in &manifest.checksums {
+        let path = dir.join(relative_path);
+        report.checked += 1;
+        let problem = match sha256_file(&path) {
+            Ok(actual) if actual == *checksum => continue,
+            Ok(_) => "checksum doesn't match".to_string(),
+            Err(err) => err.to_string(),
+        };
+        report.problems.push(ManifestProblem { path, problem });
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+
+    #[test]
+    fn test_sha256_file() {
+        let dir = TestDir::new("sha256");
+        fs::write(dir.join("abc.txt"), b"abc").unwrap();
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256_file(&dir.join("abc.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_manifest_round_trip_and_verify() {
+        let dir = TestDir::new("manifest");
+        fs::create_dir_all(dir.join("2015")).unwrap();
+        fs::write(dir.join("2015/1.jpg"), b"jpg").unwrap();
+        fs::write(dir.join("2.jpg"), b"jpg").unwrap();
+
+        let mut manifest = Manifest::default();
+        for file in [dir.join("2015/1.jpg"), dir.join("2.jpg")] {
+            let checksum = sha256_file(&file).unwrap();
+            manifest.insert(&dir, &file, checksum);
+        }
+        manifest.save(&dir).unwrap();
+        assert_eq!(manifest, Manifest::load(&dir).unwrap());
+
+        let report = verify_manifest(&dir);
+        assert_eq!(2, report.checked);
+        assert!(report.problems.is_empty());
+
+        fs::write(dir.join("2.jpg"), b"bit rot").unwrap();
+        fs::remove_file(dir.join("2015/1.jpg")).unwrap();
+        let report = verify_manifest(&dir);
+        assert_eq!(
+            vec![dir.join("2.jpg"), dir.join("2015/1.jpg")],
+            report
+                .problems
+                .iter()
+                .map(|problem| problem.path.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+}
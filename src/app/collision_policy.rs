@@ -0,0 +1,280 @@
+//! What a commit does when a destination name is already taken. The processed file, its raw
+//! companion and their sidecars are always resolved together, so a pair keeps matching names.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use super::exif::CaptureTime;
+
+/// Gives up on suffixes long before a real folder would run out of them.
+const MAX_SUFFIX: usize = 10_000;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Leaves the photo where it is.
+    Skip,
+    /// Replaces files from earlier commits. Two photos of the same commit never replace each
+    /// other, the later one gets a suffix instead.
+    Overwrite,
+    /// Appends `_1`, `_2`, ... to the file stem.
+    #[default]
+    AutoSuffix,
+    /// Renames to the capture time, e.g. `20150518_130119.jpg`, with a suffix if that's taken
+    /// too. Photos without a capture time get a suffix.
+    CaptureTimestamp,
+}
+
+impl CollisionPolicy {
+    pub const ALL: [CollisionPolicy; 4] = [
+        CollisionPolicy::Skip,
+        CollisionPolicy::Overwrite,
+        CollisionPolicy::AutoSuffix,
+        CollisionPolicy::CaptureTimestamp,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CollisionPolicy::Skip => "Skip",
+            CollisionPolicy::Overwrite => "Overwrite",
+            CollisionPolicy::AutoSuffix => "Add _1 suffix",
+            CollisionPolicy::CaptureTimestamp => "Rename to capture time",
+        }
+    }
+
+    /// Resolves the destinations of one photo, `None` when it should be skipped.
+    /// `companions` are the files that follow the photo to a set of destinations, e.g. its
+    /// sidecars, their names have to be free as well. `is_existing` tells whether a file is
+    /// already there, `is_planned` whether another photo of this commit is going there.
+    pub fn resolve(
+        self,
+        destinations: &[PathBuf],
+        capture_time: Option<&CaptureTime>,
+        companions: impl Fn(&[PathBuf]) -> Vec<PathBuf>,
+        is_existing: impl Fn(&Path) -> bool,
+        is_planned: impl Fn(&Path) -> bool,
+    ) -> Option<Vec<PathBuf>> {
+        let is_free = |candidates: &[PathBuf]| {
+            candidates
+                .iter()
+                .chain(&companions(candidates))
+                .all(|candidate| !is_existing(candidate) && !is_planned(candidate))
+        };
+        if is_free(destinations) {
+            return Some(destinations.to_vec());
+        }
+
+        let stem = match (self, capture_time) {
+            (CollisionPolicy::Skip, _) => return None,
+            (CollisionPolicy::Overwrite, _)
+                if !destinations
+                    .iter()
+                    .chain(&companions(destinations))
+                    .any(|d| is_planned(d)) =>
+            {
+                return Some(destinations.to_vec());
+            }
+            (CollisionPolicy::CaptureTimestamp, Some(capture_time)) => {
+                let stem = timestamp_stem(capture_time);
+                let renamed = with_stem(destinations, &stem);
+                if is_free(&renamed) {
+                    return Some(renamed);
+                }
+                Some(stem)
+            }
+            _ => None,
+        };
+
+        (1..=MAX_SUFFIX)
+            .map(|suffix| {
+                destinations
+                    .iter()
+                    .map(|destination| {
+                        let mut suffixed = match &stem {
+                            Some(stem) => OsString::from(stem),
+                            None => destination.file_stem().unwrap_or_default().to_owned(),
+                        };
+                        suffixed.push(format!("_{}", suffix));
+                        with_file_stem(destination, &suffixed)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .find(|candidates| is_free(candidates))
+    }
+}
+
+fn timestamp_stem(capture_time: &CaptureTime) -> String {
+    format!(
+        "{:04}{:02}{:02}_{:02}{:02}{:02}",
+        capture_time.year,
+        capture_time.month,
+        capture_time.day,
+        capture_time.hour,
+        capture_time.minute,
+        capture_time.second
+    )
+}
+
+fn with_stem(destinations: &[PathBuf], stem: &str) -> Vec<PathBuf> {
+    destinations
+        .iter()
+        .map(|destination| with_file_stem(destination, stem.as_ref()))
+        .collect()
+}
+
+/// Swaps the stem of the file name and keeps its extension.
+fn with_file_stem(path: &Path, stem: &std::ffi::OsStr) -> PathBuf {
+    let mut file_name = stem.to_owned();
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> Vec<PathBuf> {
+        vec![
+            PathBuf::from("wheat/IMG_0001.JPG"),
+            PathBuf::from("wheat/IMG_0001.CR3"),
+        ]
+    }
+
+    fn capture_time() -> CaptureTime {
+        CaptureTime::parse("2015:05:18 13:01:19", None).unwrap()
+    }
+
+    #[test]
+    fn test_free_names_stay() {
+        for policy in CollisionPolicy::ALL {
+            assert_eq!(
+                Some(pair()),
+                policy.resolve(&pair(), None, |_| Vec::new(), |_| false, |_| false)
+            );
+        }
+    }
+
+    #[test]
+    fn test_raw_collision_renames_the_pair() {
+        // Only the raw is taken, the JPEG still gets the same suffix so the pair keeps matching
+        let is_existing = |path: &Path| path == Path::new("wheat/IMG_0001.CR3");
+
+        assert_eq!(
+            None,
+            CollisionPolicy::Skip.resolve(&pair(), None, |_| Vec::new(), is_existing, |_| false)
+        );
+        assert_eq!(
+            Some(pair()),
+            CollisionPolicy::Overwrite.resolve(
+                &pair(),
+                None,
+                |_| Vec::new(),
+                is_existing,
+                |_| false
+            )
+        );
+        assert_eq!(
+            Some(vec![
+                PathBuf::from("wheat/IMG_0001_1.JPG"),
+                PathBuf::from("wheat/IMG_0001_1.CR3"),
+            ]),
+            CollisionPolicy::AutoSuffix.resolve(
+                &pair(),
+                None,
+                |_| Vec::new(),
+                is_existing,
+                |_| false
+            )
+        );
+        assert_eq!(
+            Some(vec![
+                PathBuf::from("wheat/20150518_130119.JPG"),
+                PathBuf::from("wheat/20150518_130119.CR3"),
+            ]),
+            CollisionPolicy::CaptureTimestamp.resolve(
+                &pair(),
+                Some(&capture_time()),
+                |_| Vec::new(),
+                is_existing,
+                |_| false
+            )
+        );
+    }
+
+    #[test]
+    fn test_taken_sidecar_renames_the_pair() {
+        let sidecars = |destinations: &[PathBuf]| vec![destinations[0].with_extension("xmp")];
+        let is_existing = |path: &Path| path == Path::new("wheat/IMG_0001.xmp");
+
+        assert_eq!(
+            None,
+            CollisionPolicy::Skip.resolve(&pair(), None, sidecars, is_existing, |_| false)
+        );
+        assert_eq!(
+            Some(vec![
+                PathBuf::from("wheat/IMG_0001_1.JPG"),
+                PathBuf::from("wheat/IMG_0001_1.CR3"),
+            ]),
+            CollisionPolicy::AutoSuffix.resolve(&pair(), None, sidecars, is_existing, |_| false)
+        );
+    }
+
+    #[test]
+    fn test_suffix_counts_up() {
+        let is_existing = |path: &Path| {
+            ["IMG_0001.JPG", "IMG_0001_1.JPG", "20150518_130119.JPG"]
+                .iter()
+                .any(|name| path == Path::new("wheat").join(name))
+        };
+
+        assert_eq!(
+            Some(vec![
+                PathBuf::from("wheat/IMG_0001_2.JPG"),
+                PathBuf::from("wheat/IMG_0001_2.CR3"),
+            ]),
+            CollisionPolicy::AutoSuffix.resolve(
+                &pair(),
+                None,
+                |_| Vec::new(),
+                is_existing,
+                |_| false
+            )
+        );
+        assert_eq!(
+            Some(vec![
+                PathBuf::from("wheat/20150518_130119_1.JPG"),
+                PathBuf::from("wheat/20150518_130119_1.CR3"),
+            ]),
+            CollisionPolicy::CaptureTimestamp.resolve(
+                &pair(),
+                Some(&capture_time()),
+                |_| Vec::new(),
+                is_existing,
+                |_| false
+            )
+        );
+    }
+
+    #[test]
+    fn test_overwrite_never_replaces_same_commit() {
+        let is_existing = |path: &Path| pair().iter().any(|taken| taken == path);
+        let is_planned = |path: &Path| path == Path::new("wheat/IMG_0001.JPG");
+        assert_eq!(
+            Some(vec![
+                PathBuf::from("wheat/IMG_0001_1.JPG"),
+                PathBuf::from("wheat/IMG_0001_1.CR3"),
+            ]),
+            CollisionPolicy::Overwrite.resolve(
+                &pair(),
+                None,
+                |_| Vec::new(),
+                is_existing,
+                is_planned
+            )
+        );
+    }
+}
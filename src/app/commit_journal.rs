@@ -0,0 +1,687 @@
+//! Every commit is written to `.blitz/commit_journal.ron` before the first file moves. A commit
+//! that fails halfway leaves a record of what was planned, and the last commit can be rolled
+//! back by moving everything in the journal to where it came from.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ron::ser::PrettyConfig;
+
+use super::{
+    checksum::{transfer_verified, Manifest},
+    collision_policy::CollisionPolicy,
+    commit_mode::CommitMode,
+    destination_template::DestinationTemplate,
+    models::{ImageInfo, Rating},
+    storage,
+    trash::Trash,
+    xmp,
+};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct FileMove {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct JournalEntry {
+    /// The culling state of the photo, restored when the commit is undone.
+    pub photo: ImageInfo,
+    /// The processed file first, then its raw companion.
+    pub moves: Vec<FileMove>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct CommitJournal {
+    /// Journals written before commit modes existed were always moves.
+    #[serde(default)]
+    pub mode: CommitMode,
+    pub entries: Vec<JournalEntry>,
+    /// Photos left in place because their name was taken and the policy said to skip them.
+    #[serde(default)]
+    pub skipped: Vec<PathBuf>,
+    /// The trash removed photos were sent to instead of the chaffe folder.
+    #[serde(default)]
+    pub trash: Option<Trash>,
+    /// Whether every file is hashed before and after its transfer, the checksums of the wheat
+    /// files go into the manifest of `wheat_dir`.
+    #[serde(default)]
+    pub verify_checksums: bool,
+    #[serde(default)]
+    pub wheat_dir: PathBuf,
+}
+
+/// A file the commit (or its rollback) couldn't move, shown to the user afterwards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitFailure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+impl CommitFailure {
+    pub fn new(path: &Path, error: impl ToString) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Everything the user configured about where and how a commit puts the files.
+#[derive(Clone, Debug, Default)]
+pub struct CommitOptions {
+    pub chaffe_dir: PathBuf,
+    pub wheat_dir: PathBuf,
+    pub mode: CommitMode,
+    pub collision_policy: CollisionPolicy,
+    pub template: DestinationTemplate,
+    /// Sends removed photos here instead of to the chaffe folder.
+    pub trash: Option<Trash>,
+    pub verify_checksums: bool,
+    /// The sidecars of the committed photos are written before they move.
+    pub write_sidecars: bool,
+}
+
+impl CommitJournal {
+    /// Where the journal of the last commit in `photo_dir` lives.
+    pub fn path(photo_dir: &Path) -> PathBuf {
+        let mut journal_path = photo_dir.to_path_buf();
+        journal_path.push(".blitz");
+        journal_path.push("commit_journal.ron");
+        journal_path
+    }
+
+    /// Approved photos go below the wheat folder, removed ones below the chaffe folder or into
+    /// the trash and unrated ones stay where they are.
+    pub fn plan(photos: &[ImageInfo], options: &CommitOptions) -> Self {
+        let mut journal = Self {
+            mode: options.mode,
+            trash: options.trash.clone(),
+            verify_checksums: options.verify_checksums,
+            wheat_dir: options.wheat_dir.clone(),
+            ..Default::default()
+        };
+        let mut planned_destinations = HashSet::new();
+
+        for photo in photos {
+            let destination_dir = match photo.rating {
+                Rating::Unrated => continue,
+                Rating::Approve => &options.wheat_dir,
+                Rating::Remove => &options.chaffe_dir,
+            };
+            let sources: Vec<&PathBuf> = [Some(&photo.path_processed), photo.path_raw.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter(|source| source.file_name().is_some())
+                .collect();
+            let trash = options
+                .trash
+                .as_ref()
+                .filter(|_| photo.rating == Rating::Remove);
+            let destinations: Vec<PathBuf> = sources
+                .iter()
+                .map(|source| match trash {
+                    // The trash is flat, templates don't apply there
+                    Some(trash) => trash
+                        .files_dir()
+                        .join(source.file_name().unwrap_or_default()),
+                    None => destination_dir.join(options.template.render(photo, source)),
+                })
+                .collect();
+
+            // Sidecars follow their files, a JPG and RAW pair can share one named after the stem
+            let mut sidecars: Vec<(usize, PathBuf)> = Vec::new();
+            for (index, source) in sources.iter().enumerate() {
+                for sidecar in xmp::planned_sidecars(source, options.write_sidecars) {
+                    if sidecars.iter().all(|(_, planned)| *planned != sidecar) {
+                        sidecars.push((index, sidecar));
+                    }
+                }
+            }
+            let sidecar_destinations = |destinations: &[PathBuf]| -> Vec<PathBuf> {
+                sidecars
+                    .iter()
+                    .map(|(index, sidecar)| {
+                        xmp::sidecar_destination(sidecar, sources[*index], &destinations[*index])
+                    })
+                    .collect()
+            };
+
+            // Other apps share the trash, so names there are never skipped or overwritten
+            let collision_policy = match trash {
+                Some(_) => CollisionPolicy::AutoSuffix,
+                None => options.collision_policy,
+            };
+            let Some(destinations) = collision_policy.resolve(
+                &destinations,
+                photo.metadata.capture_time.as_ref(),
+                sidecar_destinations,
+                |destination| match trash {
+                    Some(trash) => trash.is_taken(destination),
+                    None => destination.exists(),
+                },
+                |destination| planned_destinations.contains(destination),
+            ) else {
+                journal.skipped.push(photo.path_processed.clone());
+                continue;
+            };
+
+            let sidecar_moves: Vec<FileMove> = sidecars
+                .iter()
+                .zip(sidecar_destinations(&destinations))
+                .map(|((_, sidecar), destination)| FileMove {
+                    source: sidecar.clone(),
+                    destination,
+                })
+                .collect();
+            let mut moves: Vec<FileMove> = sources
+                .into_iter()
+                .zip(destinations)
+                .map(|(source, destination)| FileMove {
+                    source: source.clone(),
+                    destination,
+                })
+                .collect();
+            moves.extend(sidecar_moves);
+            planned_destinations
+                .extend(moves.iter().map(|file_move| file_move.destination.clone()));
+            journal.entries.push(JournalEntry {
+                photo: photo.clone(),
+                moves,
+            });
+        }
+        journal
+    }
+
+    /// The trash the file went to, if it was trashed rather than committed.
+    fn trash_for(&self, file_move: &FileMove) -> Option<&Trash> {
+        self.trash
+            .as_ref()
+            .filter(|trash| trash.contains(&file_move.destination))
+    }
+
+    /// How the file gets to its destination, trashing is always a move.
+    pub fn mode_for(&self, file_move: &FileMove) -> CommitMode {
+        match self.trash_for(file_move) {
+            Some(_) => CommitMode::Move,
+            None => self.mode,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(journal_path: &Path) -> io::Result<Self> {
+        let serialized_ron = fs::read(journal_path)?;
+        ron::de::from_bytes(&serialized_ron).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, journal_path: &Path) -> io::Result<()> {
+        if let Some(blitz_dir) = journal_path.parent() {
+            fs::create_dir_all(blitz_dir)?;
+        }
+        let ron_str =
+            ron::ser::to_string_pretty(self, PrettyConfig::new()).map_err(io::Error::other)?;
+        // A truncated journal couldn't undo the commit
+        storage::write_atomically(journal_path, ron_str.as_bytes())
+    }
+
+    /// Transfers every file of the journal in its commit mode, creating the destination folders
+    /// on the way. When any file of a photo fails, the ones already transferred are put back
+    /// and the rest isn't touched, so pairs and their sidecars never get split.
+    pub fn apply(&self) -> Vec<CommitFailure> {
+        let mut failures = Vec::new();
+        let mut manifest = Manifest::default();
+        for entry in &self.entries {
+            let mut checksums = Vec::new();
+            for (applied, file_move) in entry.moves.iter().enumerate() {
+                let moved = file_move
+                    .destination
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| match self.trash_for(file_move) {
+                        Some(trash) => trash
+                            .put(&file_move.source, &file_move.destination)
+                            .map(|_| None),
+                        None if self.verify_checksums => {
+                            transfer_verified(self.mode, &file_move.source, &file_move.destination)
+                                .map(Some)
+                        }
+                        None => self
+                            .mode
+                            .transfer(&file_move.source, &file_move.destination)
+                            .map(|_| None),
+                    });
+                match moved {
+                    Ok(Some(checksum)) => checksums.push((&file_move.destination, checksum)),
+                    Ok(None) => {}
+                    Err(err) => {
+                        failures.push(CommitFailure::new(&file_move.source, err));
+                        for earlier_move in entry.moves[..applied].iter().rev() {
+                            if let Err(failure) = self.undo_move(earlier_move) {
+                                failures.push(failure);
+                            }
+                        }
+                        checksums.clear();
+                        break;
+                    }
+                }
+            }
+            for (destination, checksum) in checksums {
+                manifest.insert(&self.wheat_dir, destination, checksum);
+            }
+        }
+
+        // Chaffe files are verified too, but only the wheat folder keeps a manifest
+        if !manifest.checksums.is_empty() {
+            let saved = Manifest::load(&self.wheat_dir).and_then(|mut existing| {
+                existing.checksums.append(&mut manifest.checksums);
+                existing.save(&self.wheat_dir)
+            });
+            if let Err(err) = saved {
+                failures.push(CommitFailure::new(&Manifest::path(&self.wheat_dir), err));
+            }
+        }
+        failures
+    }
+
+    /// Moves every file back to where it was before the commit, newest move first, or removes
+    /// the copies and links when the originals never left. Files that never left or are
+    /// already back are skipped, so a rollback can be retried.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn roll_back(&self) -> Vec<CommitFailure> {
+        let mut failures = Vec::new();
+        for file_move in self
+            .entries
+            .iter()
+            .rev()
+            .flat_map(|entry| entry.moves.iter().rev())
+        {
+            if let Err(failure) = self.undo_move(file_move) {
+                failures.push(failure);
+            }
+        }
+
+        if self.verify_checksums {
+            if let Err(err) = self.forget_checksums() {
+                failures.push(CommitFailure::new(&Manifest::path(&self.wheat_dir), err));
+            }
+        }
+        failures
+    }
+
+    /// Puts the file of `file_move` back where it was, or removes the copy or link when the
+    /// original never left. A file that never left or is already back is left alone.
+    fn undo_move(&self, file_move: &FileMove) -> Result<(), CommitFailure> {
+        if !self.mode_for(file_move).removes_source() {
+            // The originals never left, only the copies and links have to go
+            let is_present = fs::symlink_metadata(&file_move.destination).is_ok();
+            if is_present {
+                fs::remove_file(&file_move.destination)
+                    .map_err(|err| CommitFailure::new(&file_move.destination, err))?;
+            }
+            return Ok(());
+        }
+        if !file_move.destination.exists() {
+            if !file_move.source.exists() {
+                return Err(CommitFailure::new(&file_move.source, "file is missing"));
+            }
+            return Ok(());
+        }
+        if file_move.source.exists() {
+            return Err(CommitFailure::new(
+                &file_move.source,
+                "another file took its place",
+            ));
+        }
+        file_move
+            .source
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| match self.trash_for(file_move) {
+                Some(trash) => trash.restore(&file_move.destination, &file_move.source),
+                None => super::commit_mode::move_file(&file_move.destination, &file_move.source),
+            })
+            .map_err(|err| CommitFailure::new(&file_move.destination, err))
+    }
+
+    /// Drops the files that are gone from the wheat folder again from its manifest.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn forget_checksums(&self) -> io::Result<()> {
+        let mut manifest = Manifest::load(&self.wheat_dir)?;
+        if manifest.checksums.is_empty() {
+            return Ok(());
+        }
+        for file_move in self.entries.iter().flat_map(|entry| &entry.moves) {
+            if fs::symlink_metadata(&file_move.destination).is_err() {
+                manifest.remove(&self.wheat_dir, &file_move.destination);
+            }
+        }
+        manifest.save(&self.wheat_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+
+    fn photo(path_processed: PathBuf, path_raw: Option<PathBuf>, rating: Rating) -> ImageInfo {
+        ImageInfo {
+            image_name: path_processed.file_name().unwrap().to_string_lossy().into(),
+            path_processed,
+            path_raw,
+            rating,
+            ..Default::default()
+        }
+    }
+
+    fn options(chaffe_dir: &Path, wheat_dir: &Path) -> CommitOptions {
+        CommitOptions {
+            chaffe_dir: chaffe_dir.to_path_buf(),
+            wheat_dir: wheat_dir.to_path_buf(),
+            collision_policy: CollisionPolicy::Skip,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_roll_back_restores_commit() {
+        let photo_dir = TestDir::new("journal_roll_back");
+        let wheat_dir = photo_dir.join("wheat");
+        let chaffe_dir = photo_dir.join("chaffe");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        fs::create_dir_all(&chaffe_dir).unwrap();
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+        fs::write(photo_dir.join("1.raf"), b"raf").unwrap();
+        fs::write(photo_dir.join("2.jpg"), b"jpg").unwrap();
+
+        let photos = vec![
+            photo(
+                photo_dir.join("1.jpg"),
+                Some(photo_dir.join("1.raf")),
+                Rating::Approve,
+            ),
+            photo(photo_dir.join("2.jpg"), None, Rating::Remove),
+        ];
+        let journal = CommitJournal::plan(&photos, &options(&chaffe_dir, &wheat_dir));
+        let journal_path = CommitJournal::path(&photo_dir);
+        journal.save(&journal_path).unwrap();
+
+        assert_eq!(Vec::<CommitFailure>::new(), journal.apply());
+        assert!(wheat_dir.join("1.raf").exists());
+        assert!(chaffe_dir.join("2.jpg").exists());
+
+        let journal = CommitJournal::load(&journal_path).unwrap();
+        assert_eq!(Vec::<CommitFailure>::new(), journal.roll_back());
+        assert!(photo_dir.join("1.jpg").exists());
+        assert!(photo_dir.join("1.raf").exists());
+        assert!(photo_dir.join("2.jpg").exists());
+        assert!(!wheat_dir.join("1.jpg").exists());
+
+        // Running it again finds everything already back in place
+        assert_eq!(Vec::<CommitFailure>::new(), journal.roll_back());
+    }
+
+    #[test]
+    fn test_roll_back_removes_copies() {
+        let photo_dir = TestDir::new("journal_copies");
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+
+        let photos = vec![photo(photo_dir.join("1.jpg"), None, Rating::Approve)];
+        let wheat_dir = photo_dir.join("wheat");
+        let journal = CommitJournal::plan(
+            &photos,
+            &CommitOptions {
+                mode: CommitMode::Copy,
+                ..options(&photo_dir, &wheat_dir)
+            },
+        );
+
+        assert_eq!(Vec::<CommitFailure>::new(), journal.apply());
+        assert!(wheat_dir.join("1.jpg").exists());
+        assert_eq!(Vec::<CommitFailure>::new(), journal.roll_back());
+        assert!(!wheat_dir.join("1.jpg").exists());
+        assert!(photo_dir.join("1.jpg").exists());
+    }
+
+    #[test]
+    fn test_plan_resolves_collisions_for_pairs() {
+        let photo_dir = TestDir::new("journal_collisions");
+        let wheat_dir = photo_dir.join("wheat");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        fs::write(wheat_dir.join("1.raf"), b"older raf").unwrap();
+
+        let photos = vec![photo(
+            photo_dir.join("1.jpg"),
+            Some(photo_dir.join("1.raf")),
+            Rating::Approve,
+        )];
+        let plan = |collision_policy| {
+            let options = CommitOptions {
+                collision_policy,
+                ..options(&photo_dir, &wheat_dir)
+            };
+            CommitJournal::plan(&photos, &options)
+        };
+
+        let skipping = plan(CollisionPolicy::Skip);
+        assert!(skipping.entries.is_empty());
+        assert_eq!(vec![photo_dir.join("1.jpg")], skipping.skipped);
+
+        let suffixing = plan(CollisionPolicy::AutoSuffix);
+        let destinations: Vec<&PathBuf> = suffixing.entries[0]
+            .moves
+            .iter()
+            .map(|file_move| &file_move.destination)
+            .collect();
+        assert_eq!(
+            vec![&wheat_dir.join("1_1.jpg"), &wheat_dir.join("1_1.raf")],
+            destinations
+        );
+    }
+
+    #[test]
+    fn test_apply_keeps_pairs_together() {
+        let photo_dir = TestDir::new("journal_pairs");
+        fs::write(photo_dir.join("1.raf"), b"raf").unwrap();
+
+        // The processed file is missing, so the raw companion has to stay where it is
+        let photos = vec![photo(
+            photo_dir.join("1.jpg"),
+            Some(photo_dir.join("1.raf")),
+            Rating::Approve,
+        )];
+        let wheat_dir = photo_dir.join("wheat");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        let failures = CommitJournal::plan(&photos, &options(&photo_dir, &wheat_dir)).apply();
+
+        assert_eq!(1, failures.len());
+        assert_eq!(photo_dir.join("1.jpg"), failures[0].path);
+        assert!(photo_dir.join("1.raf").exists());
+    }
+
+    #[test]
+    fn test_apply_puts_back_the_processed_file_when_the_raw_fails() {
+        let photo_dir = TestDir::new("journal_split_pair");
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+
+        // The processed file moves first, then its raw companion turns out to be missing
+        let photos = vec![photo(
+            photo_dir.join("1.jpg"),
+            Some(photo_dir.join("1.raf")),
+            Rating::Approve,
+        )];
+        let wheat_dir = photo_dir.join("wheat");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        let failures = CommitJournal::plan(&photos, &options(&photo_dir, &wheat_dir)).apply();
+
+        assert_eq!(1, failures.len());
+        assert_eq!(photo_dir.join("1.raf"), failures[0].path);
+        assert_eq!(b"jpg".to_vec(), fs::read(photo_dir.join("1.jpg")).unwrap());
+        assert!(!wheat_dir.join("1.jpg").exists());
+    }
+
+    #[test]
+    fn test_sidecars_follow_their_files() {
+        let photo_dir = TestDir::new("journal_sidecars");
+        let wheat_dir = photo_dir.join("wheat");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+        fs::write(photo_dir.join("1.raf"), b"raf").unwrap();
+        fs::write(photo_dir.join("1.xmp"), b"xmp").unwrap();
+        fs::write(photo_dir.join("1.raf.xmp"), b"xmp").unwrap();
+
+        let photos = vec![photo(
+            photo_dir.join("1.jpg"),
+            Some(photo_dir.join("1.raf")),
+            Rating::Approve,
+        )];
+        let journal = CommitJournal::plan(&photos, &options(&photo_dir, &wheat_dir));
+        let destinations: Vec<&PathBuf> = journal.entries[0]
+            .moves
+            .iter()
+            .map(|file_move| &file_move.destination)
+            .collect();
+        assert_eq!(
+            vec![
+                &wheat_dir.join("1.jpg"),
+                &wheat_dir.join("1.raf"),
+                &wheat_dir.join("1.xmp"),
+                &wheat_dir.join("1.raf.xmp"),
+            ],
+            destinations
+        );
+
+        assert!(journal.apply().is_empty());
+        assert!(wheat_dir.join("1.xmp").exists());
+        assert!(!photo_dir.join("1.raf.xmp").exists());
+    }
+
+    #[test]
+    fn test_plan_moves_sidecars_about_to_be_written() {
+        let photo_dir = Path::new("/card");
+        let wheat_dir = photo_dir.join("wheat");
+        let photos = vec![photo(photo_dir.join("1.jpg"), None, Rating::Approve)];
+        let options = CommitOptions {
+            write_sidecars: true,
+            ..options(photo_dir, &wheat_dir)
+        };
+
+        let journal = CommitJournal::plan(&photos, &options);
+        assert_eq!(
+            FileMove {
+                source: photo_dir.join("1.jpg.xmp"),
+                destination: wheat_dir.join("1.jpg.xmp"),
+            },
+            journal.entries[0].moves[1]
+        );
+    }
+
+    #[test]
+    fn test_plan_resolves_collisions_for_sidecars() {
+        let photo_dir = TestDir::new("journal_sidecar_collisions");
+        let wheat_dir = photo_dir.join("wheat");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        fs::write(photo_dir.join("1.xmp"), b"xmp").unwrap();
+        fs::write(wheat_dir.join("1.xmp"), b"older xmp").unwrap();
+
+        let photos = vec![photo(photo_dir.join("1.jpg"), None, Rating::Approve)];
+        let plan = |collision_policy| {
+            let options = CommitOptions {
+                collision_policy,
+                ..options(&photo_dir, &wheat_dir)
+            };
+            CommitJournal::plan(&photos, &options)
+        };
+
+        let skipping = plan(CollisionPolicy::Skip);
+        assert!(skipping.entries.is_empty());
+        assert_eq!(vec![photo_dir.join("1.jpg")], skipping.skipped);
+
+        let suffixing = plan(CollisionPolicy::AutoSuffix);
+        assert_eq!(
+            vec![
+                FileMove {
+                    source: photo_dir.join("1.jpg"),
+                    destination: wheat_dir.join("1_1.jpg"),
+                },
+                FileMove {
+                    source: photo_dir.join("1.xmp"),
+                    destination: wheat_dir.join("1_1.xmp"),
+                },
+            ],
+            suffixing.entries[0].moves
+        );
+    }
+
+    #[test]
+    fn test_rejects_go_to_trash() {
+        let photo_dir = TestDir::new("journal_trash");
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+        fs::write(photo_dir.join("1.raf"), b"raf").unwrap();
+        let trash = Trash {
+            dir: photo_dir.join("Trash"),
+        };
+        // An older trashed 1.jpg still has its info, so the pair gets a suffix
+        fs::create_dir_all(trash.dir.join("info")).unwrap();
+        fs::write(trash.dir.join("info/1.jpg.trashinfo"), b"[Trash Info]").unwrap();
+
+        let photos = vec![photo(
+            photo_dir.join("1.jpg"),
+            Some(photo_dir.join("1.raf")),
+            Rating::Remove,
+        )];
+        let journal = CommitJournal::plan(
+            &photos,
+            &CommitOptions {
+                mode: CommitMode::Copy,
+                trash: Some(trash.clone()),
+                ..options(&photo_dir.join("chaffe"), &photo_dir.join("wheat"))
+            },
+        );
+
+        assert_eq!(Vec::<CommitFailure>::new(), journal.apply());
+        assert!(!photo_dir.join("1.jpg").exists());
+        assert!(trash.files_dir().join("1_1.jpg").exists());
+        assert!(trash.files_dir().join("1_1.raf").exists());
+        assert!(trash.dir.join("info/1_1.raf.trashinfo").exists());
+
+        assert_eq!(Vec::<CommitFailure>::new(), journal.roll_back());
+        assert!(photo_dir.join("1.jpg").exists());
+        assert!(photo_dir.join("1.raf").exists());
+        assert!(!trash.dir.join("info/1_1.jpg.trashinfo").exists());
+    }
+
+    #[test]
+    fn test_verified_commit_writes_manifest() {
+        let photo_dir = TestDir::new("journal_manifest");
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+        fs::write(photo_dir.join("2.jpg"), b"jpg").unwrap();
+
+        let photos = vec![
+            photo(photo_dir.join("1.jpg"), None, Rating::Approve),
+            photo(photo_dir.join("2.jpg"), None, Rating::Remove),
+        ];
+        let wheat_dir = photo_dir.join("wheat");
+        let journal = CommitJournal::plan(
+            &photos,
+            &CommitOptions {
+                verify_checksums: true,
+                ..options(&photo_dir.join("chaffe"), &wheat_dir)
+            },
+        );
+
+        assert_eq!(Vec::<CommitFailure>::new(), journal.apply());
+        let manifest = Manifest::load(&wheat_dir).unwrap();
+        assert_eq!(
+            vec![&PathBuf::from("1.jpg")],
+            manifest.checksums.keys().collect::<Vec<_>>()
+        );
+
+        assert_eq!(Vec::<CommitFailure>::new(), journal.roll_back());
+        assert!(Manifest::load(&wheat_dir).unwrap().checksums.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+//! How a committed file gets to its destination. Moving is the default, the other modes leave
+//! the card untouched or avoid duplicating the bytes.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommitMode {
+    #[default]
+    Move,
+    CopyVerifyDelete,
+    Copy,
+    HardLink,
+    Symlink,
+}
+
+impl CommitMode {
+    pub const ALL: [CommitMode; 5] = [
+        CommitMode::Move,
+        CommitMode::CopyVerifyDelete,
+        CommitMode::Copy,
+        CommitMode::HardLink,
+        CommitMode::Symlink,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CommitMode::Move => "Move",
+            CommitMode::CopyVerifyDelete => "Copy, verify, then delete",
+            CommitMode::Copy => "Copy",
+            CommitMode::HardLink => "Hard link",
+            CommitMode::Symlink => "Symlink",
+        }
+    }
+
+    /// Whether the source file is gone once the commit is done.
+    pub fn removes_source(self) -> bool {
+        matches!(self, CommitMode::Move | CommitMode::CopyVerifyDelete)
+    }
+
+    /// Whether the bytes of the file get written to the destination volume.
+    pub fn writes_bytes(self, same_volume: bool) -> bool {
+        match self {
+            CommitMode::Move => !same_volume,
+            CommitMode::CopyVerifyDelete | CommitMode::Copy => true,
+            CommitMode::HardLink | CommitMode::Symlink => false,
+        }
+    }
+
+    pub fn transfer(self, source: &Path, destination: &Path) -> io::Result<()> {
+        self.transfer_checked(source, destination, &|_| Ok(()))
+    }
+
+    /// Transfers the file and runs `check` on the destination while the source is still there.
+    /// A destination that fails the check is moved back or removed again.
+    pub fn transfer_checked(
+        self,
+        source: &Path,
+        destination: &Path,
+        check: &dyn Fn(&Path) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match self {
+            CommitMode::Move => match fs::rename(source, destination) {
+                Ok(()) => check(destination).inspect_err(|_| {
+                    let _ = fs::rename(destination, source);
+                }),
+                Err(err) if is_cross_device(&err) => copy_verify_delete(source, destination, check),
+                Err(err) => Err(err),
+            },
+            CommitMode::CopyVerifyDelete => copy_verify_delete(source, destination, check),
+            CommitMode::Copy => copy_verified(source, destination, check),
+            CommitMode::HardLink => {
+                remove_overwritten(destination)?;
+                fs::hard_link(source, destination)?;
+                remove_unless(destination, check)
+            }
+            CommitMode::Symlink => {
+                remove_overwritten(destination)?;
+                symlink(&fs::canonicalize(source)?, destination)?;
+                remove_unless(destination, check)
+            }
+        }
+    }
+}
+
+/// Renames the file, falling back to copy and delete when it has to cross volumes, e.g. from
+/// an SD card to a NAS mount.
+pub fn move_file(source: &Path, destination: &Path) -> io::Result<()> {
+    CommitMode::Move.transfer(source, destination)
+}
+
+/// Links can't replace a file the way renames and copies do, so the old one goes first.
+fn remove_overwritten(destination: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(destination) {
+        Ok(_) => fs::remove_file(destination),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Keeps the transferred file only when it passes `check`.
+fn remove_unless(destination: &Path, check: &dyn Fn(&Path) -> io::Result<()>) -> io::Result<()> {
+    check(destination).inspect_err(|_| {
+        let _ = fs::remove_file(destination);
+    })
+}
+
+fn copy_verify_delete(
+    source: &Path,
+    destination: &Path,
+    check: &dyn Fn(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    copy_verified(source, destination, check)?;
+    fs::remove_file(source)
+}
+
+/// Copies the file and reads both back, a copy that doesn't match is removed again.
+fn copy_verified(
+    source: &Path,
+    destination: &Path,
+    check: &dyn Fn(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    fs::copy(source, destination)?;
+    remove_unless(destination, &|copy| match files_match(source, copy)? {
+        true => check(copy),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the copy doesn't match the original",
+        )),
+    })
+}
+
+fn files_match(first: &Path, second: &Path) -> io::Result<bool> {
+    let mut first = io::BufReader::new(fs::File::open(first)?);
+    let mut second = io::BufReader::new(fs::File::open(second)?);
+    let mut first_chunk = [0; 64 * 1024];
+    let mut second_chunk = [0; 64 * 1024];
+    loop {
+        let read = first.read(&mut first_chunk)?;
+        if read == 0 {
+            return Ok(second.read(&mut second_chunk)? == 0);
+        }
+        if second.read_exact(&mut second_chunk[..read]).is_err()
+            || first_chunk[..read] != second_chunk[..read]
+        {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_cross_device(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EXDEV)
+}
+
+#[cfg(windows)]
+fn is_cross_device(err: &io::Error) -> bool {
+    // ERROR_NOT_SAME_DEVICE
+    err.raw_os_error() == Some(17)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_cross_device(_err: &io::Error) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_original: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks aren't supported here",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+
+    #[test]
+    fn test_transfer_modes() {
+        let dir = TestDir::new("commit_modes");
+        fs::write(dir.join("1.jpg"), b"jpg").unwrap();
+        let source = dir.join("1.jpg");
+
+        CommitMode::Copy
+            .transfer(&source, &dir.join("copy.jpg"))
+            .unwrap();
+        CommitMode::HardLink
+            .transfer(&source, &dir.join("hard_link.jpg"))
+            .unwrap();
+        #[cfg(unix)]
+        {
+            CommitMode::Symlink
+                .transfer(&source, &dir.join("symlink.jpg"))
+                .unwrap();
+            assert!(fs::symlink_metadata(dir.join("symlink.jpg"))
+                .unwrap()
+                .file_type()
+                .is_symlink());
+        }
+        assert!(source.exists());
+        assert_eq!(b"jpg".to_vec(), fs::read(dir.join("copy.jpg")).unwrap());
+        assert_eq!(
+            b"jpg".to_vec(),
+            fs::read(dir.join("hard_link.jpg")).unwrap()
+        );
+
+        CommitMode::CopyVerifyDelete
+            .transfer(&source, &dir.join("moved.jpg"))
+            .unwrap();
+        assert!(!source.exists());
+        assert_eq!(b"jpg".to_vec(), fs::read(dir.join("moved.jpg")).unwrap());
+    }
+
+    #[test]
+    fn test_failed_check_keeps_source() {
+        let dir = TestDir::new("commit_mode_check");
+        fs::write(dir.join("1.jpg"), b"jpg").unwrap();
+        let source = dir.join("1.jpg");
+        let reject = |_: &Path| Err(io::Error::other("corrupted"));
+
+        for mode in CommitMode::ALL {
+            let destination = dir.join("checked.jpg");
+            assert!(mode
+                .transfer_checked(&source, &destination, &reject)
+                .is_err());
+            assert_eq!(b"jpg".to_vec(), fs::read(&source).unwrap());
+            assert!(fs::symlink_metadata(&destination).is_err());
+        }
+    }
+
+    #[test]
+    fn test_files_match() {
+        let dir = TestDir::new("files_match");
+        fs::write(dir.join("1.jpg"), b"jpg").unwrap();
+        fs::write(dir.join("same.jpg"), b"jpg").unwrap();
+        fs::write(dir.join("longer.jpg"), b"jpg and more").unwrap();
+        fs::write(dir.join("other.jpg"), b"png").unwrap();
+
+        assert!(files_match(&dir.join("1.jpg"), &dir.join("same.jpg")).unwrap());
+        assert!(!files_match(&dir.join("1.jpg"), &dir.join("longer.jpg")).unwrap());
+        assert!(!files_match(&dir.join("longer.jpg"), &dir.join("1.jpg")).unwrap());
+        assert!(!files_match(&dir.join("1.jpg"), &dir.join("other.jpg")).unwrap());
+    }
+}
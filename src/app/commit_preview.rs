@@ -0,0 +1,288 @@
+//! Looks at a commit plan before anything moves: what goes where, which names clash and
+//! whether the destination volumes have room for it.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::commit_journal::CommitJournal;
+
+pub struct DestinationSummary {
+    pub dir: PathBuf,
+    pub photo_count: usize,
+    pub file_count: usize,
+    /// Bytes that have to be written there, e.g. moves within a volume and links need none.
+    pub bytes_needed: u64,
+    /// What all destinations on the same volume need together, `bytes_free` is shared by them.
+    pub volume_bytes_needed: u64,
+    pub bytes_free: Option<u64>,
+    /// The device the folder is on, `None` where that isn't known.
+    volume: Option<u64>,
+}
+
+impl DestinationSummary {
+    pub fn has_room(&self) -> bool {
+        self.bytes_free
+            .map_or(true, |bytes_free| self.volume_bytes_needed <= bytes_free)
+    }
+}
+
+pub struct CommitPreview {
+    /// The plan the preview was made for, it is committed as is once confirmed.
+    pub journal: CommitJournal,
+    pub destinations: Vec<DestinationSummary>,
+    /// Destinations that already exist or that more than one file would be moved to.
+    pub collisions: Vec<PathBuf>,
+}
+
+impl CommitPreview {
+    pub fn new(journal: CommitJournal) -> Self {
+        let mut destinations: Vec<DestinationSummary> = Vec::new();
+        let mut collisions = Vec::new();
+        let mut planned_destinations = HashSet::new();
+
+        for entry in &journal.entries {
+            for (index, file_move) in entry.moves.iter().enumerate() {
+                let Some(dir) = file_move.destination.parent() else {
+                    continue;
+                };
+                let summary = match destinations.iter_mut().position(|d| d.dir == dir) {
+                    Some(position) => &mut destinations[position],
+                    None => {
+                        destinations.push(DestinationSummary {
+                            dir: dir.to_path_buf(),
+                            photo_count: 0,
+                            file_count: 0,
+                            bytes_needed: 0,
+                            volume_bytes_needed: 0,
+                            bytes_free: free_space(dir),
+                            volume: volume(dir),
+                        });
+                        destinations.last_mut().unwrap()
+                    }
+                };
+                // The processed file comes first, raw companions don't count as extra photos
+                if index == 0 {
+                    summary.photo_count += 1;
+                }
+                summary.file_count += 1;
+                if journal
+                    .mode_for(file_move)
+                    .writes_bytes(is_same_volume(&file_move.source, dir))
+                {
+                    summary.bytes_needed += fs::metadata(&file_move.source)
+                        .map(|metadata| metadata.len())
+                        .unwrap_or(0);
+                }
+
+                let is_duplicate = !planned_destinations.insert(file_move.destination.clone());
+                if is_duplicate || file_move.destination.exists() {
+                    collisions.push(file_move.destination.clone());
+                }
+            }
+        }
+
+        // Wheat and chaffe on one volume can each fit while both together don't
+        let needed_per_volume: Vec<u64> = destinations
+            .iter()
+            .map(|destination| match destination.volume {
+                Some(volume) => destinations
+                    .iter()
+                    .filter(|other| other.volume == Some(volume))
+                    .map(|other| other.bytes_needed)
+                    .sum(),
+                None => destination.bytes_needed,
+            })
+            .collect();
+        for (destination, needed) in destinations.iter_mut().zip(needed_per_volume) {
+            destination.volume_bytes_needed = needed;
+        }
+
+        Self {
+            journal,
+            destinations,
+            collisions,
+        }
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.destinations.iter().map(|d| d.file_count).sum()
+    }
+
+    pub fn can_commit(&self) -> bool {
+        self.file_count() > 0 && self.destinations.iter().all(DestinationSummary::has_room)
+    }
+}
+
+/// Formats a byte count the way file managers do, e.g. `12.3 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// The closest folder that exists, destinations are only created when committing.
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|ancestor| ancestor.exists())
+}
+
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let dir = CString::new(existing_ancestor(dir)?.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `dir` is NUL terminated and `stats` is only read after statvfs filled it in
+    let stats = unsafe {
+        if libc::statvfs(dir.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stats.assume_init()
+    };
+    #[allow(clippy::unnecessary_cast)] // the field types differ between platforms
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).map(|metadata| metadata.dev()).ok()
+}
+
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// The device `dir` will be created on.
+fn volume(dir: &Path) -> Option<u64> {
+    existing_ancestor(dir).and_then(device)
+}
+
+fn is_same_volume(source: &Path, dir: &Path) -> bool {
+    match (device(source), volume(dir)) {
+        (Some(source_device), Some(dir_device)) => source_device == dir_device,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::commit_journal::{FileMove, JournalEntry};
+    use crate::app::commit_mode::CommitMode;
+    use crate::app::models::ImageInfo;
+    use crate::app::test_dir::TestDir;
+
+    fn entry(moves: &[(&Path, &Path)]) -> JournalEntry {
+        JournalEntry {
+            photo: ImageInfo::default(),
+            moves: moves
+                .iter()
+                .map(|(source, destination)| FileMove {
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_preview_counts_and_collisions() {
+        let photo_dir = TestDir::new("commit_preview");
+        let wheat_dir = photo_dir.join("wheat");
+        let chaffe_dir = photo_dir.join("chaffe");
+        fs::create_dir_all(&wheat_dir).unwrap();
+        fs::write(photo_dir.join("1.jpg"), b"jpg").unwrap();
+        fs::write(photo_dir.join("1.raf"), b"raf").unwrap();
+        fs::write(wheat_dir.join("1.raf"), b"older raf").unwrap();
+
+        let journal = CommitJournal {
+            mode: CommitMode::Move,
+            entries: vec![
+                entry(&[
+                    (&photo_dir.join("1.jpg"), &wheat_dir.join("1.jpg")),
+                    (&photo_dir.join("1.raf"), &wheat_dir.join("1.raf")),
+                ]),
+                entry(&[(&photo_dir.join("a/2.jpg"), &chaffe_dir.join("2.jpg"))]),
+                entry(&[(&photo_dir.join("b/2.jpg"), &chaffe_dir.join("2.jpg"))]),
+            ],
+            ..Default::default()
+        };
+        let preview = CommitPreview::new(journal);
+
+        assert_eq!(2, preview.destinations.len());
+        assert_eq!(1, preview.destinations[0].photo_count);
+        assert_eq!(2, preview.destinations[0].file_count);
+        assert_eq!(2, preview.destinations[1].photo_count);
+        assert_eq!(4, preview.file_count());
+        assert_eq!(
+            vec![wheat_dir.join("1.raf"), chaffe_dir.join("2.jpg")],
+            preview.collisions
+        );
+        // Renames within the temp dir don't need any space
+        #[cfg(unix)]
+        assert_eq!(0, preview.destinations[0].bytes_needed);
+        assert!(preview.can_commit());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_destinations_on_one_volume_share_its_space() {
+        let photo_dir = TestDir::new("preview_volume");
+        fs::write(photo_dir.join("1.jpg"), [0; 100]).unwrap();
+        fs::write(photo_dir.join("2.jpg"), [0; 50]).unwrap();
+
+        let journal = CommitJournal {
+            mode: CommitMode::Copy,
+            entries: vec![
+                entry(&[(&photo_dir.join("1.jpg"), &photo_dir.join("wheat/1.jpg"))]),
+                entry(&[(&photo_dir.join("2.jpg"), &photo_dir.join("chaffe/2.jpg"))]),
+            ],
+            ..Default::default()
+        };
+        let preview = CommitPreview::new(journal);
+
+        assert_eq!(100, preview.destinations[0].bytes_needed);
+        assert_eq!(150, preview.destinations[0].volume_bytes_needed);
+        assert_eq!(150, preview.destinations[1].volume_bytes_needed);
+    }
+
+    #[test]
+    fn test_has_room() {
+        let summary = |bytes_needed, bytes_free| DestinationSummary {
+            dir: PathBuf::new(),
+            photo_count: 1,
+            file_count: 1,
+            bytes_needed,
+            volume_bytes_needed: bytes_needed,
+            bytes_free,
+            volume: None,
+        };
+        assert!(summary(10, Some(10)).has_room());
+        assert!(!summary(11, Some(10)).has_room());
+        assert!(summary(11, None).has_room());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!("999 B", format_bytes(999));
+        assert_eq!("12.3 MB", format_bytes(12_345_678));
+    }
+}
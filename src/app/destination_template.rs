@@ -0,0 +1,301 @@
+//! Templates for where committed files end up below the wheat or chaffe folder, e.g.
+//! `{year}/{month}-{day}_{camera}/{rating}/{original_name}`. Fields are filled in per file from
+//! its EXIF data and culling state when the commit is planned.
+
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use super::models::{ImageInfo, Rating};
+
+/// Keeps the flat layout blitz always had.
+pub const DEFAULT_TEMPLATE: &str = "{original_name}";
+
+/// Used for fields the photo has no value for, e.g. `{lens}` on a phone picture.
+const UNKNOWN_VALUE: &str = "unknown";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Camera,
+    Make,
+    Model,
+    Lens,
+    Iso,
+    Rating,
+    Label,
+    Verdict,
+    OriginalName,
+    Stem,
+    Extension,
+}
+
+/// Every field with its name in templates and what it's replaced with.
+pub const FIELDS: [(&str, &str); 17] = [
+    ("year", "capture year, e.g. 2024"),
+    ("month", "capture month, 01 to 12"),
+    ("day", "capture day, 01 to 31"),
+    ("hour", "capture hour, 00 to 23"),
+    ("minute", "capture minute"),
+    ("second", "capture second"),
+    ("camera", "make and model, e.g. Canon EOS R5"),
+    ("make", "camera make"),
+    ("model", "camera model"),
+    ("lens", "lens model"),
+    ("iso", "ISO speed"),
+    ("rating", "stars, 0 to 5"),
+    ("label", "color label, or none"),
+    ("verdict", "keep or reject"),
+    ("original_name", "file name with extension"),
+    ("stem", "file name without extension"),
+    ("ext", "file extension"),
+];
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "hour" => Field::Hour,
+            "minute" => Field::Minute,
+            "second" => Field::Second,
+            "camera" => Field::Camera,
+            "make" => Field::Make,
+            "model" => Field::Model,
+            "lens" => Field::Lens,
+            "iso" => Field::Iso,
+            "rating" => Field::Rating,
+            "label" => Field::Label,
+            "verdict" => Field::Verdict,
+            "original_name" => Field::OriginalName,
+            "stem" => Field::Stem,
+            "ext" => Field::Extension,
+            _ => return None,
+        })
+    }
+
+    fn value(self, photo: &ImageInfo, source: &Path) -> Option<String> {
+        let metadata = &photo.metadata;
+        let capture_time = metadata.capture_time.as_ref();
+        let file_part =
+            |part: Option<&std::ffi::OsStr>| part.map(|part| part.to_string_lossy().into_owned());
+        match self {
+            Field::Year => capture_time.map(|time| format!("{:04}", time.year)),
+            Field::Month => capture_time.map(|time| format!("{:02}", time.month)),
+            Field::Day => capture_time.map(|time| format!("{:02}", time.day)),
+            Field::Hour => capture_time.map(|time| format!("{:02}", time.hour)),
+            Field::Minute => capture_time.map(|time| format!("{:02}", time.minute)),
+            Field::Second => capture_time.map(|time| format!("{:02}", time.second)),
+            Field::Camera => metadata.camera(),
+            Field::Make => metadata.camera_make.clone(),
+            Field::Model => metadata.camera_model.clone(),
+            Field::Lens => metadata.lens.clone(),
+            Field::Iso => metadata.iso.map(|iso| iso.to_string()),
+            Field::Rating => Some(photo.stars.to_string()),
+            Field::Label => Some(
+                photo
+                    .label
+                    .map_or("none", |label| label.name())
+                    .to_lowercase(),
+            ),
+            Field::Verdict => match photo.rating {
+                Rating::Unrated => None,
+                Rating::Approve => Some("keep".to_string()),
+                Rating::Remove => Some("reject".to_string()),
+            },
+            Field::OriginalName => file_part(source.file_name()),
+            Field::Stem => file_part(source.file_stem()),
+            Field::Extension => file_part(source.extension()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    UnknownField(String),
+    UnclosedBrace,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownField(name) => write!(f, "unknown field {{{}}}", name),
+            TemplateError::UnclosedBrace => write!(f, "a {{ is never closed"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DestinationTemplate {
+    segments: Vec<Segment>,
+}
+
+impl Default for DestinationTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).unwrap()
+    }
+}
+
+impl DestinationTemplate {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or(TemplateError::UnclosedBrace)?;
+            let name = &rest[start + 1..start + end];
+            if name.contains('{') {
+                return Err(TemplateError::UnclosedBrace);
+            }
+            let field = Field::from_name(name.trim())
+                .ok_or_else(|| TemplateError::UnknownField(name.to_string()))?;
+            segments.push(Segment::Field(field));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// The path of `source`, the processed file or raw companion of `photo`, relative to the
+    /// wheat or chaffe folder. The file always keeps its extension, so a processed file and its
+    /// raw never end up with the same name.
+    pub fn render(&self, photo: &ImageInfo, source: &Path) -> PathBuf {
+        let rendered: String = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(field) => field
+                    .value(photo, source)
+                    .map(|value| sanitize(&value))
+                    .filter(|value| !value.is_empty())
+                    .unwrap_or_else(|| UNKNOWN_VALUE.to_string()),
+            })
+            .collect();
+
+        // Only plain folder names, a template can't climb out of the destination folder
+        let mut relative_path: PathBuf = Path::new(&rendered)
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        if relative_path.as_os_str().is_empty() {
+            relative_path.push(source.file_name().unwrap_or_default());
+        }
+
+        if let Some(extension) = source.extension() {
+            let has_extension = relative_path
+                .extension()
+                .is_some_and(|rendered| rendered.eq_ignore_ascii_case(extension));
+            if !has_extension {
+                let mut file_name = relative_path.file_name().unwrap_or_default().to_owned();
+                file_name.push(".");
+                file_name.push(extension);
+                relative_path.set_file_name(file_name);
+            }
+        }
+        relative_path
+    }
+}
+
+/// Field values are single folder or file name parts, e.g. a `/` in a lens name can't start
+/// a new folder.
+fn sanitize(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::exif::{CaptureTime, ImageMetadata};
+    use crate::app::models::ColorLabel;
+
+    fn photo() -> ImageInfo {
+        ImageInfo {
+            path_processed: PathBuf::from("/card/IMG_0001.JPG"),
+            rating: Rating::Approve,
+            stars: 4,
+            label: Some(ColorLabel::Green),
+            metadata: ImageMetadata {
+                camera_make: Some("Canon".to_string()),
+                camera_model: Some("Canon EOS-1D X".to_string()),
+                lens: Some("EF70-200mm f/2.8L IS II USM".to_string()),
+                capture_time: CaptureTime::parse("2015:05:18 13:01:19", None),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_example_template() {
+        let template =
+            DestinationTemplate::parse("{year}/{month}-{day}_{camera}/{rating}/{original_name}")
+                .unwrap();
+        assert_eq!(
+            PathBuf::from("2015/05-18_Canon EOS-1D X/4/IMG_0001.JPG"),
+            template.render(&photo(), Path::new("/card/IMG_0001.JPG"))
+        );
+        assert_eq!(
+            PathBuf::from("2015/05-18_Canon EOS-1D X/4/IMG_0001.CR2"),
+            template.render(&photo(), Path::new("/card/IMG_0001.CR2"))
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_extension_and_sanitizes() {
+        let template = DestinationTemplate::parse("{label}/{lens}_{verdict}").unwrap();
+        assert_eq!(
+            PathBuf::from("green/EF70-200mm f_2.8L IS II USM_keep.JPG"),
+            template.render(&photo(), Path::new("/card/IMG_0001.JPG"))
+        );
+
+        let template = DestinationTemplate::parse("../{iso}/{stem}.{ext}").unwrap();
+        assert_eq!(
+            PathBuf::from("unknown/IMG_0001.CR2"),
+            template.render(&photo(), Path::new("/card/IMG_0001.CR2"))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Err(TemplateError::UnknownField("yaer".to_string())),
+            DestinationTemplate::parse("{yaer}/{original_name}")
+        );
+        assert_eq!(
+            Err(TemplateError::UnclosedBrace),
+            DestinationTemplate::parse("{year/{original_name}")
+        );
+        assert_eq!(
+            PathBuf::from("IMG_0001.JPG"),
+            DestinationTemplate::default().render(&photo(), Path::new("/card/IMG_0001.JPG"))
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Keeps the thumbnails and screen-fit images decoded for a folder in `.blitz/cache`, so
+//! reopening a large folder doesn't decode every photo again. Entries are named after the
+//! fingerprint and modification time of their photo, so an edited photo just misses the cache.
+//! Past the size limit the least recently used entries are deleted, until the cache is a
+//! tenth below it.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{codecs::jpeg::JpegEncoder, DynamicImage};
+
+use super::{models::ImageInfo, storage, tiers::Tier, BlitzApp};
+
+const CACHE_EXTENSION: &str = "jpg";
+
+/// Good enough for something that is scaled down anyway, at a fraction of the size.
+const JPEG_QUALITY: u8 = 90;
+
+pub const DEFAULT_LIMIT_MB: u64 = 500;
+
+/// Entries waiting to be written, more are dropped and only miss the cache next time. Screen-fit
+/// images are large enough that the queue shouldn't grow with the decoders.
+const MAX_QUEUED_STORES: usize = 8;
+
+/// How full the cache is after trimming, in percent of the limit, so the next stores don't
+/// trim again right away.
+const LOW_WATER_PERCENT: u64 = 90;
+
+#[derive(Default)]
+struct State {
+    /// `.blitz/cache` of the open folder.
+    dir: Option<PathBuf>,
+    /// Taken from the folder scan, keyed by the processed file.
+    fingerprints: HashMap<PathBuf, String>,
+    /// The files in `dir`, so trimming doesn't have to list it.
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// What the entries in `dir` add up to, in bytes.
+    size: u64,
+    limit: u64,
+}
+
+impl State {
+    fn insert(&mut self, path: PathBuf, entry: CacheEntry) {
+        if let Some(replaced) = self.entries.insert(path, entry) {
+            self.size -= replaced.len;
+        }
+        self.size += entry.len;
+    }
+
+    /// Takes the least recently used entries out until the cache is below its low-water mark,
+    /// the files are left to [`delete`] once the state is unlocked again.
+    fn trim(&mut self) -> Vec<PathBuf> {
+        if self.size <= self.limit {
+            return Vec::new();
+        }
+        let low_water = (self.limit as u128 * LOW_WATER_PERCENT as u128 / 100) as u64;
+        let mut by_use: Vec<(PathBuf, CacheEntry)> = self.entries.drain().collect();
+        by_use.sort_by_key(|(_, entry)| Reverse(entry.used));
+        let mut trimmed = Vec::new();
+        while self.size > low_water {
+            let Some((path, entry)) = by_use.pop() else {
+                break;
+            };
+            self.size -= entry.len;
+            trimmed.push(path);
+        }
+        self.entries = by_use.into_iter().collect();
+        trimmed
+    }
+}
+
+struct Store {
+    path: PathBuf,
+    tier: Tier,
+    screen_size: u32,
+    image: DynamicImage,
+}
+
+/// Shared by the image loader and the prefetch workers, which read and write entries on their
+/// own threads.
+pub struct DiskCache {
+    state: Mutex<State>,
+    /// Feeds the thread started by the first [`Self::store_in_background`].
+    stores: OnceLock<SyncSender<Store>>,
+}
+
+impl Default for DiskCache {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State {
+                limit: DEFAULT_LIMIT_MB * 1024 * 1024,
+                ..Default::default()
+            }),
+            stores: OnceLock::new(),
+        }
+    }
+}
+
+impl DiskCache {
+    /// Switches to the cache of `photo_dir`, whose `photos` were just scanned.
+    pub fn open(&self, photo_dir: &Path, photos: &[ImageInfo]) {
+        let dir = photo_dir.join(".blitz").join("cache");
+        let entries = entries(&dir);
+        let mut state = self.state.lock().unwrap();
+        state.size = entries.values().map(|entry| entry.len).sum();
+        state.entries = entries;
+        state.dir = Some(dir);
+        state.fingerprints = photos
+            .iter()
+            .filter_map(|photo| Some((photo.path_processed.clone(), photo.fingerprint.clone()?)))
+            .collect();
+        let trimmed = state.trim();
+        drop(state);
+        delete(trimmed);
+    }
+
+    /// In bytes, trims the cache right away if it's over.
+    pub fn set_limit(&self, limit: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.limit = limit;
+        let trimmed = state.trim();
+        drop(state);
+        delete(trimmed);
+    }
+
+    /// In bytes.
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().size
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some(dir) = &state.dir else {
+            return Ok(());
+        };
+        match fs::remove_dir_all(dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        state.entries.clear();
+        state.size = 0;
+        Ok(())
+    }
+
+    /// `tier` of the photo at `path` as it was stored, still in the orientation of the file.
+    pub fn load(&self, path: &Path, tier: Tier, screen_size: u32) -> Option<DynamicImage> {
+        let entry_path = self.entry_path(path, tier, screen_size)?;
+        let data = fs::read(&entry_path).ok()?;
+        // Marks the entry as recently used for trimming, on disk for the next session too
+        let used = SystemTime::now();
+        if let Some(entry) = self.state.lock().unwrap().entries.get_mut(&entry_path) {
+            entry.used = used;
+        }
+        if let Ok(file) = fs::File::options().write(true).open(&entry_path) {
+            let _ = file.set_modified(used);
+        }
+        image::load_from_memory(&data).ok()
+    }
+
+    pub fn store(&self, path: &Path, tier: Tier, screen_size: u32, image: &DynamicImage) {
+        let Some(entry_path) = self.entry_path(path, tier, screen_size) else {
+            return;
+        };
+        let mut data = Vec::new();
+        let encoded = JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())
+            .map_err(io::Error::other);
+        let written = encoded
+            .and_then(|_| fs::create_dir_all(entry_path.parent().unwrap_or(Path::new("."))))
+            .and_then(|_| storage::write_atomically(&entry_path, &data));
+        if let Err(err) = written {
+            log::warn!("Couldn't cache {}: {}", entry_path.display(), err);
+            return;
+        }
+
+        let entry = CacheEntry {
+            len: data.len() as u64,
+            used: SystemTime::now(),
+        };
+        let mut state = self.state.lock().unwrap();
+        state.insert(entry_path, entry);
+        let trimmed = state.trim();
+        drop(state);
+        delete(trimmed);
+    }
+
+    /// [`Self::store`] on a thread of its own, encoding and writing takes longer than a frame.
+    pub fn store_in_background(
+        self: &Arc<Self>,
+        path: PathBuf,
+        tier: Tier,
+        screen_size: u32,
+        image: DynamicImage,
+    ) {
+        let stores = self.stores.get_or_init(|| {
+            let (stores, receiver) = mpsc::sync_channel::<Store>(MAX_QUEUED_STORES);
+            // Stops along with the cache, which owns the sender
+            let disk_cache = Arc::downgrade(self);
+            let spawned = thread::Builder::new()
+                .name("disk cache".to_string())
+                .spawn(move || {
+                    for store in receiver {
+                        let Some(disk_cache) = disk_cache.upgrade() else {
+                            return;
+                        };
+                        disk_cache.store(&store.path, store.tier, store.screen_size, &store.image);
+                    }
+                });
+            if let Err(err) = spawned {
+                log::warn!("Couldn't start caching: {}", err);
+            }
+            stores
+        });
+        let store = Store {
+            path,
+            tier,
+            screen_size,
+            image,
+        };
+        if let Err(TrySendError::Full(store)) = stores.try_send(store) {
+            log::debug!("Not caching {}, the disk is behind", store.path.display());
+        }
+    }
+
+    /// Where `tier` of the photo at `path` is cached, `None` for photos the folder scan
+    /// didn't fingerprint.
+    fn entry_path(&self, path: &Path, tier: Tier, screen_size: u32) -> Option<PathBuf> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        let modified = modified.duration_since(UNIX_EPOCH).ok()?.as_millis();
+        let state = self.state.lock().unwrap();
+        let fingerprint = state.fingerprints.get(path)?;
+        // Screen-fit images of another monitor aren't of any use
+        let tier_name = match tier {
+            Tier::Screen => format!("{}{}", tier.name(), screen_size),
+            _ => tier.name().to_string(),
+        };
+        let name = format!(
+            "{}-{}-{}.{}",
+            fingerprint, modified, tier_name, CACHE_EXTENSION
+        );
+        Some(state.dir.as_ref()?.join(name))
+    }
+}
+
+fn delete(files: Vec<PathBuf>) {
+    for file in files {
+        let _ = fs::remove_file(file);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CacheEntry {
+    len: u64,
+    used: SystemTime,
+}
+
+fn entries(dir: &Path) -> HashMap<PathBuf, CacheEntry> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    read_dir
+        .flatten()
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension == CACHE_EXTENSION)
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let cache_entry = CacheEntry {
+                len: metadata.len(),
+                used: metadata.modified().ok()?,
+            };
+            Some((entry.path(), cache_entry))
+        })
+        .collect()
+}
+
+impl BlitzApp {
+    pub fn clear_disk_cache(&self) {
+        if let Err(err) = self.disk_cache.clear() {
+            log::warn!("Couldn't clear the cache of {:?}: {}", self.photo_dir, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+    use crate::app::tiers;
+
+    #[test]
+    fn test_entries_are_invalidated_and_trimmed() {
+        let dir = TestDir::new("disk_cache");
+        let photo_path = dir.join("1.jpg");
+        let data = fs::read("assets/samples/1.jpg").unwrap();
+        fs::write(&photo_path, &data).unwrap();
+        let photo = ImageInfo {
+            path_processed: photo_path.clone(),
+            fingerprint: Some("abc".to_string()),
+            ..Default::default()
+        };
+
+        let cache = DiskCache::default();
+        cache.open(&dir, &[photo]);
+        assert!(cache.load(&photo_path, Tier::Thumbnail, 1000).is_none());
+        let thumbnail = tiers::scale_down(&data, Tier::Thumbnail, 1000).unwrap();
+        cache.store(&photo_path, Tier::Thumbnail, 1000, &thumbnail);
+        let cached = cache.load(&photo_path, Tier::Thumbnail, 1000).unwrap();
+        assert_eq!(thumbnail.width(), cached.width());
+        assert!(cache.size() > 0);
+        // Another tier or monitor size is another entry
+        assert!(cache.load(&photo_path, Tier::Screen, 1000).is_none());
+
+        // Touching the photo changes its modification time
+        let file = fs::File::options().write(true).open(&photo_path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert!(cache.load(&photo_path, Tier::Thumbnail, 1000).is_none());
+
+        cache.store(&photo_path, Tier::Thumbnail, 1000, &thumbnail);
+        cache.set_limit(0);
+        assert_eq!(0, cache.size());
+        assert!(cache.load(&photo_path, Tier::Thumbnail, 1000).is_none());
+
+        cache.set_limit(u64::MAX);
+        cache.store(&photo_path, Tier::Thumbnail, 1000, &thumbnail);
+        cache.clear().unwrap();
+        assert!(!dir.join(".blitz").join("cache").exists());
+    }
+
+    #[test]
+    fn test_trim_goes_below_the_limit() {
+        let used = |secs| UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let mut state = State {
+            limit: 99,
+            ..Default::default()
+        };
+        for (name, len, used) in [("a", 10, used(1)), ("b", 10, used(2)), ("c", 80, used(3))] {
+            state.insert(PathBuf::from(name), CacheEntry { len, used });
+        }
+
+        assert_eq!(vec![PathBuf::from("a"), PathBuf::from("b")], state.trim());
+        assert_eq!(80, state.size);
+        assert_eq!(
+            vec![&PathBuf::from("c")],
+            state.entries.keys().collect::<Vec<_>>()
+        );
+        assert!(state.trim().is_empty());
+    }
+}
@@ -0,0 +1,375 @@
+//! Reads the capture metadata cameras write into the EXIF block of JPEGs and RAW files.
+
+use super::{
+    raw_preview,
+    tiff::{Ifd, Tiff, TAG_JPEG_INTERCHANGE_FORMAT, TAG_JPEG_INTERCHANGE_FORMAT_LENGTH},
+};
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_XMP: u16 = 0x02BC;
+const TAG_RATING: u16 = 0x4746;
+const TAG_RATING_PERCENT: u16 = 0x4749;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_F_NUMBER: u16 = 0x829D;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_LENS_MODEL: u16 = 0xA434;
+
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+
+const JPEG_APP1: u8 = 0xE1;
+const JPEG_START_OF_SCAN: u8 = 0xDA;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+#[derive(Clone, Debug, Default)]
+pub struct ImageMetadata {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens: Option<String>,
+    /// In millimeters.
+    pub focal_length: Option<f64>,
+    pub f_number: Option<f64>,
+    /// In seconds, kept as the rational the camera wrote so 1/250 doesn't turn into 0.004.
+    pub exposure_time: Option<(u32, u32)>,
+    pub iso: Option<u32>,
+    pub capture_time: Option<CaptureTime>,
+    /// The EXIF orientation value, 1 to 8.
+    pub orientation: Option<u16>,
+    /// Latitude and longitude in decimal degrees, south and west are negative.
+    pub gps: Option<(f64, f64)>,
+    /// The stars given on the camera back or in Windows, 0 to 5 and −1 for rejects.
+    pub rating: Option<i8>,
+}
+
+impl ImageMetadata {
+    /// Make and model, without repeating the make when the model already starts with it.
+    pub fn camera(&self) -> Option<String> {
+        match (&self.camera_make, &self.camera_model) {
+            (Some(make), Some(model)) if model.starts_with(make.as_str()) => Some(model.clone()),
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => make.clone().or_else(|| model.clone()),
+        }
+    }
+
+    pub fn exposure_time_label(&self) -> Option<String> {
+        let (numerator, denominator) = self.exposure_time?;
+        if numerator == 0 || denominator == 0 {
+            return None;
+        }
+        if numerator >= denominator {
+            let seconds = f64::from(numerator) / f64::from(denominator);
+            return Some(format!("{} s", (seconds * 10.0).round() / 10.0));
+        }
+        let reciprocal = (f64::from(denominator) / f64::from(numerator)).round();
+        Some(format!("1/{} s", reciprocal))
+    }
+
+    pub fn orientation_label(&self) -> Option<&'static str> {
+        Some(match self.orientation? {
+            1 => "Normal",
+            2 => "Mirrored",
+            3 => "Rotated 180°",
+            4 => "Mirrored vertically",
+            5 => "Mirrored, rotated 90° CCW",
+            6 => "Rotated 90° CW",
+            7 => "Mirrored, rotated 90° CW",
+            8 => "Rotated 90° CCW",
+            _ => return None,
+        })
+    }
+}
+
+/// `DateTimeOriginal` with the optional sub-second part, in camera local time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CaptureTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millisecond: u32,
+}
+
+impl CaptureTime {
+    /// Parses the EXIF `YYYY:MM:DD HH:MM:SS` format, `sub_sec` holds the decimal fraction digits.
+    pub fn parse(date_time: &str, sub_sec: Option<&str>) -> Option<Self> {
+        let (date, time) = date_time.trim().split_once(' ')?;
+        let mut date = date.split(':').map(str::parse::<u32>);
+        let mut time = time.split(':').map(str::parse::<u32>);
+        let year = date.next()?.ok()? as i32;
+        let month = date.next()?.ok()?;
+        let day = date.next()?.ok()?;
+        let hour = time.next()?.ok()?;
+        let minute = time.next()?.ok()?;
+        let second = time.next()?.ok()?;
+        if year == 0 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+
+        let millisecond = sub_sec
+            .map(|digits| digits.trim())
+            .filter(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+            .map(|digits| {
+                let digits: String = digits.chars().chain("00".chars()).take(3).collect();
+                digits.parse().unwrap_or(0)
+            })
+            .unwrap_or(0);
+
+        Some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millisecond,
+        })
+    }
+
+    /// Milliseconds since 1970-01-01, only meaningful for comparing capture times.
+    pub fn as_millis(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let seconds = days * 86_400
+            + i64::from(self.hour) * 3_600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second);
+        seconds * 1_000 + i64::from(self.millisecond)
+    }
+}
+
+impl std::fmt::Display for CaptureTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        if self.millisecond > 0 {
+            write!(f, ".{:03}", self.millisecond)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads what we can from a JPEG, a TIFF based RAW or a RAF file. Missing or broken EXIF
+/// data just leaves the fields empty.
+pub fn read_metadata(data: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    let Some(tiff) = find_exif_tiff(data).and_then(Tiff::parse) else {
+        return metadata;
+    };
+    let Some(ifd0) = tiff
+        .first_ifd_offset()
+        .and_then(|offset| tiff.read_ifd(offset))
+    else {
+        return metadata;
+    };
+
+    let ascii = |ifd: &Ifd, tag: u16| {
+        ifd.get(tag)
+            .and_then(|entry| tiff.entry_ascii(entry))
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+    };
+    let float = |ifd: &Ifd, tag: u16| ifd.get(tag).and_then(|entry| tiff.entry_f64(entry));
+    let sub_ifd = |tag: u16| {
+        ifd0.get(tag)
+            .and_then(|entry| tiff.entry_u32(entry))
+            .and_then(|offset| tiff.read_ifd(offset as usize))
+    };
+
+    metadata.camera_make = ascii(&ifd0, TAG_MAKE);
+    metadata.camera_model = ascii(&ifd0, TAG_MODEL);
+    metadata.orientation = ifd0
+        .get(TAG_ORIENTATION)
+        .and_then(|entry| tiff.entry_u32(entry))
+        .and_then(|orientation| u16::try_from(orientation).ok());
+    let integer = |tag: u16| ifd0.get(tag).and_then(|entry| tiff.entry_u32(entry));
+    metadata.rating = match (integer(TAG_RATING), integer(TAG_RATING_PERCENT)) {
+        // A SHORT, so a reject written as −1 reads back as 65535
+        (Some(rating), _) => Some((rating as u16 as i16).clamp(-1, 5) as i8),
+        (None, Some(percent)) => Some(match percent {
+            0 => 0,
+            1..=24 => 1,
+            25..=49 => 2,
+            50..=74 => 3,
+            75..=98 => 4,
+            _ => 5,
+        }),
+        (None, None) => None,
+    };
+
+    if let Some(exif_ifd) = sub_ifd(TAG_EXIF_IFD) {
+        let sub_sec = ascii(&exif_ifd, TAG_SUB_SEC_TIME_ORIGINAL);
+        metadata.capture_time = ascii(&exif_ifd, TAG_DATE_TIME_ORIGINAL)
+            .and_then(|date_time| CaptureTime::parse(&date_time, sub_sec.as_deref()));
+        metadata.lens = ascii(&exif_ifd, TAG_LENS_MODEL);
+        metadata.focal_length = float(&exif_ifd, TAG_FOCAL_LENGTH);
+        metadata.f_number = float(&exif_ifd, TAG_F_NUMBER);
+        metadata.exposure_time = exif_ifd
+            .get(TAG_EXPOSURE_TIME)
+            .and_then(|entry| tiff.entry_rationals(entry).first().copied());
+        metadata.iso = exif_ifd
+            .get(TAG_ISO)
+            .and_then(|entry| tiff.entry_u32(entry));
+    }
+
+    if let Some(gps_ifd) = sub_ifd(TAG_GPS_IFD) {
+        let coordinate = |value_tag: u16, ref_tag: u16, negative_ref: &str| {
+            let rationals = tiff.entry_rationals(gps_ifd.get(value_tag)?);
+            let [degrees, minutes, seconds] = rationals.get(..3)? else {
+                return None;
+            };
+            let as_f64 = |(numerator, denominator): (u32, u32)| {
+                (denominator != 0).then(|| f64::from(numerator) / f64::from(denominator))
+            };
+            let value = as_f64(*degrees)? + as_f64(*minutes)? / 60.0 + as_f64(*seconds)? / 3_600.0;
+            match ascii(&gps_ifd, ref_tag) {
+                Some(reference) if reference == negative_ref => Some(-value),
+                _ => Some(value),
+            }
+        };
+        metadata.gps = coordinate(TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, "S").zip(coordinate(
+            TAG_GPS_LONGITUDE,
+            TAG_GPS_LONGITUDE_REF,
+            "W",
+        ));
+    }
+
+    metadata
+}
+
+/// The XMP packet embedded in a JPEG or RAW file, where cameras and editors keep ratings that
+/// don't fit into EXIF.
+pub fn find_embedded_xmp(data: &[u8]) -> Option<&str> {
+    let packet = if data.starts_with(b"II") || data.starts_with(b"MM") {
+        let tiff = Tiff::parse(data)?;
+        let ifd0 = tiff.read_ifd(tiff.first_ifd_offset()?)?;
+        tiff.entry_bytes(ifd0.get(TAG_XMP)?)?
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        find_app1_segment(data, XMP_HEADER)?
+    } else {
+        return raw_preview::raf_preview_start(data).and_then(find_embedded_xmp);
+    };
+    std::str::from_utf8(packet).ok()
+}
+
+/// The small JPEG cameras keep in the second IFD of the EXIF block for file browsers, often
+/// just 160 pixels wide but decoded in no time.
+pub fn find_exif_thumbnail(data: &[u8]) -> Option<&[u8]> {
+    let tiff_data = find_exif_tiff(data)?;
+    let tiff = Tiff::parse(tiff_data)?;
+    let ifd0 = tiff.read_ifd(tiff.first_ifd_offset()?)?;
+    let ifd1 = tiff.read_ifd(ifd0.next_ifd_offset?)?;
+    let offset = tiff.entry_u32(ifd1.get(TAG_JPEG_INTERCHANGE_FORMAT)?)? as usize;
+    let length = tiff.entry_u32(ifd1.get(TAG_JPEG_INTERCHANGE_FORMAT_LENGTH)?)? as usize;
+    tiff_data
+        .get(offset..offset.checked_add(length)?)
+        .filter(|jpeg| jpeg.starts_with(&[0xFF, 0xD8]))
+}
+
+/// The TIFF structure holding the EXIF tags, RAW files are TIFFs themselves while JPEGs (and
+/// the preview inside a RAF) keep it in their APP1 segment. `data` may be just the start of
+/// the file.
+fn find_exif_tiff(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"II") || data.starts_with(b"MM") {
+        return Some(data);
+    }
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return raw_preview::raf_preview_start(data).and_then(find_exif_tiff);
+    }
+    find_app1_segment(data, EXIF_HEADER)
+}
+
+/// The content of the first APP1 segment starting with `header`, without the header.
+fn find_app1_segment<'a>(data: &'a [u8], header: &[u8]) -> Option<&'a [u8]> {
+    let mut position = 2;
+    while let Some(&[0xFF, marker, high, low]) = data.get(position..position + 4) {
+        if marker == JPEG_START_OF_SCAN {
+            break;
+        }
+        let length = u16::from_be_bytes([high, low]) as usize;
+        let segment = data.get(position + 4..position + 2 + length)?;
+        if marker == JPEG_APP1 && segment.starts_with(header) {
+            return Some(&segment[header.len()..]);
+        }
+        position += 2 + length;
+    }
+    None
+}
+
+/// Days between 1970-01-01 and the given date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_time_parse() {
+        let capture_time = CaptureTime::parse("2024:06:01 13:45:10", Some("25")).unwrap();
+        assert_eq!(2024, capture_time.year);
+        assert_eq!(10, capture_time.second);
+        assert_eq!(250, capture_time.millisecond);
+
+        assert_eq!(None, CaptureTime::parse("0000:00:00 00:00:00", None));
+        assert_eq!(None, CaptureTime::parse("garbage", None));
+    }
+
+    #[test]
+    fn test_capture_time_as_millis() {
+        let epoch = CaptureTime::parse("1970:01:01 00:00:00", None).unwrap();
+        assert_eq!(0, epoch.as_millis());
+
+        let before = CaptureTime::parse("2023:12:31 23:59:59", Some("9")).unwrap();
+        let after = CaptureTime::parse("2024:01:01 00:00:00", Some("1")).unwrap();
+        assert_eq!(200, after.as_millis() - before.as_millis());
+    }
+
+    #[test]
+    fn test_read_metadata_from_jpeg() {
+        let data = std::fs::read("assets/samples/1.jpg").unwrap();
+        let metadata = read_metadata(&data);
+        assert_eq!(
+            CaptureTime::parse("2015:05:18 13:01:19", Some("77")),
+            metadata.capture_time
+        );
+        assert_eq!(Some("Canon EOS-1D X".to_string()), metadata.camera());
+        assert_eq!(
+            Some("EF70-200mm f/2.8L IS II USM".to_string()),
+            metadata.lens
+        );
+        assert_eq!(Some(102.0), metadata.focal_length);
+        assert_eq!(Some(2.8), metadata.f_number);
+        assert_eq!(Some("1/2500 s".to_string()), metadata.exposure_time_label());
+        assert_eq!(Some(1250), metadata.iso);
+        assert_eq!(Some(1), metadata.orientation);
+        assert_eq!(None, metadata.gps);
+    }
+
+    #[test]
+    fn test_read_metadata_without_exif() {
+        let metadata = read_metadata(&[0xFF, 0xD8, 0xFF, 0xD9]);
+        assert_eq!(None, metadata.capture_time);
+    }
+}
@@ -6,127 +6,196 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use models::{ImageInfo, Rating};
+use commit_journal::{CommitFailure, CommitJournal, CommitOptions};
+use commit_preview::CommitPreview;
+use destination_template::DestinationTemplate;
+use history::History;
+use models::ImageInfo;
 use ron::ser::PrettyConfig;
+use storage::FolderSettings;
+use trash::Trash;
 
 impl BlitzApp {
+    /// Plans the commit and opens the preview, nothing touches the disk until it's confirmed.
+    pub fn preview_commit(&mut self) {
+        let options = get_commit_options(self);
+
+        if let Ok(photos) = self.photos.try_read() {
+            let journal = CommitJournal::plan(&photos, &options);
+            self.commit_preview = Some(CommitPreview::new(journal));
+        }
+    }
+
+    /// Commits the plan confirmed in the preview.
     #[allow(unused_variables)]
-    pub fn commit_choices(&mut self, ui: &mut egui::Ui) {
-        let chaffe_dir = &get_chaffe_dir(self);
-        let wheat_dir = &get_wheat_dir(self);
+    pub fn commit_choices(&mut self, ui: &mut egui::Ui, journal: CommitJournal) {
+        // The plan moves a sidecar for every committed photo, so they all get written below
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.write_xmp_sidecars {
+            for entry in &journal.entries {
+                self.sidecar_ratings.remove(&entry.photo.path_processed);
+            }
+        }
+        if let Some(photos) = self.photos.try_read().ok().map(|photos| photos.clone()) {
+            // The rescan below restores ratings from disk and the journal moves the sidecars,
+            // so they have to be there first
+            self.save_now(photos);
+        }
 
-        match fs::create_dir_all(chaffe_dir.clone()) {
-            Ok(it) => log::debug!("Created {:?} as chaffe_dir", chaffe_dir.as_os_str()),
-            Err(_err) => todo!("handle when we can't make directories later"),
+        let journal_path = CommitJournal::path(&self.photo_dir);
+        self.commit_failures = match commit_culling(&journal, &journal_path) {
+            Ok(failures) => failures,
+            Err(err) => vec![CommitFailure::new(&journal_path, err)],
         };
 
-        match fs::create_dir_all(wheat_dir.clone()) {
-            Ok(it) => log::debug!("Created {:?} as wheat_dir", wheat_dir.as_os_str()),
-            Err(_err) => todo!("handle when we can't make directories later"),
+        #[cfg(not(target_arch = "wasm32"))]
+        self.open_folder_action(ui, self.photo_dir.clone());
+    }
+
+    /// Moves the files of the last commit back using its journal, along with their ratings.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn undo_last_commit(&mut self, ui: &mut egui::Ui) {
+        let journal_path = CommitJournal::path(&self.photo_dir);
+        let journal = match CommitJournal::load(&journal_path) {
+            Ok(journal) => journal,
+            Err(err) => {
+                self.commit_failures = vec![CommitFailure::new(&journal_path, err)];
+                return;
+            }
         };
 
-        if let Ok(photos) = self.photos.try_read() {
-            commit_culling(&photos, chaffe_dir, wheat_dir);
+        self.commit_failures = journal.roll_back();
+        if self.commit_failures.is_empty() {
+            let _ = fs::remove_file(&journal_path);
         }
 
-        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(mut photos) = self.photos.try_read().ok().map(|photos| photos.clone()) {
+            photos.extend(journal.entries.into_iter().map(|entry| entry.photo));
+            self.save_now(photos);
+        }
         self.open_folder_action(ui, self.photo_dir.clone());
     }
+
+    /// Writes `photos` and the history, there are no threads to autosave on the web.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save_now(&mut self, photos: Vec<ImageInfo>) {
+        let _ = save_culling_progress(&self.photo_dir, &photos, &self.folder_settings());
+        if !photos.is_empty() {
+            let _ = save_history(&self.photo_dir, &self.history);
+        }
+    }
+
+    /// The choices saved with the culling progress of the folder.
+    pub fn folder_settings(&self) -> FolderSettings {
+        FolderSettings {
+            wheat_dir: self.wheat_dir_target.clone(),
+            chaffe_dir: self.chaffe_dir_target.clone(),
+        }
+    }
+
+    /// Checks the wheat folder against the manifest written by verified commits.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn verify_wheat_dir(&mut self) {
+        self.verify_report = Some(checksum::verify_manifest(&get_wheat_dir(self)));
+    }
+}
+
+fn get_commit_options(app: &BlitzApp) -> CommitOptions {
+    let template = DestinationTemplate::parse(&app.destination_template).unwrap_or_else(|err| {
+        log::warn!("Ignoring the destination template: {}", err);
+        DestinationTemplate::default()
+    });
+    let trash = match app.trash_rejects {
+        true => Trash::home().or_else(|| {
+            log::warn!("No trash found, rejects go to the chaffe folder");
+            None
+        }),
+        false => None,
+    };
+    CommitOptions {
+        chaffe_dir: get_chaffe_dir(app),
+        wheat_dir: get_wheat_dir(app),
+        mode: app.commit_mode,
+        collision_policy: app.collision_policy,
+        template,
+        trash,
+        verify_checksums: app.verify_checksums,
+        // The web version has no sidecars
+        write_sidecars: cfg!(not(target_arch = "wasm32")) && app.write_xmp_sidecars,
+    }
 }
 
-fn get_chaffe_dir(template_app: &BlitzApp) -> PathBuf {
-    match &template_app.chaffe_dir_target {
+pub fn get_chaffe_dir(app: &BlitzApp) -> PathBuf {
+    match &app.chaffe_dir_target {
         Some(target_dir) => target_dir.clone(),
         None => {
-            let mut chaffe_dir = template_app.photo_dir.to_path_buf();
+            let mut chaffe_dir = app.photo_dir.to_path_buf();
             chaffe_dir.push("chaffe");
             chaffe_dir
         }
     }
 }
 
-fn get_wheat_dir(template_app: &BlitzApp) -> PathBuf {
-    match &template_app.wheat_dir_target {
+pub fn get_wheat_dir(app: &BlitzApp) -> PathBuf {
+    match &app.wheat_dir_target {
         Some(target_dir) => target_dir.clone(),
         None => {
-            let mut wheat_dir = template_app.photo_dir.to_path_buf();
+            let mut wheat_dir = app.photo_dir.to_path_buf();
             wheat_dir.push("wheat");
             wheat_dir
         }
     }
 }
 
-fn commit_culling(
-    photos: &[ImageInfo],
-    chaffe_dir: &Path,
-    wheat_dir: &Path,
-) -> Vec<Result<(), io::Error>> {
-    let mut committing_results = vec![];
-    for image in photos.iter() {
-        handle_image_cull(chaffe_dir, wheat_dir, &mut committing_results, image);
-    }
-    committing_results
-}
-
-fn handle_image_cull(
-    chaffe_dir: &Path,
-    wheat_dir: &Path,
-    committing_results: &mut Vec<Result<(), io::Error>>,
-    image: &ImageInfo,
-) {
-    match image.rating {
-        Rating::Unrated => {}
-        Rating::Approve => {
-            committing_results.push(move_image_into_dir(wheat_dir, image));
-        }
-        Rating::Remove => {
-            committing_results.push(move_image_into_dir(chaffe_dir, image));
-        }
-    }
-}
-
-fn move_image_into_dir(destination_dir: &Path, image: &ImageInfo) -> Result<(), std::io::Error> {
-    let mut processed_image_destination = destination_dir.to_path_buf();
-    processed_image_destination.push(image.image_name.clone());
-    fs::rename(image.path_processed.clone(), processed_image_destination)?;
-
-    if let Some(path_raw) = &image.path_raw {
-        let mut raw_image_destination = destination_dir.to_path_buf();
-        raw_image_destination.push(image.image_name.clone());
-        raw_image_destination.set_extension("RAF");
-        fs::rename(path_raw.clone(), raw_image_destination)?;
-    }
-    Ok(())
+/// Writes the journal for the commit and then moves the files, returning the ones that
+/// couldn't be moved. Nothing is touched when the journal can't be written.
+fn commit_culling(journal: &CommitJournal, journal_path: &Path) -> io::Result<Vec<CommitFailure>> {
+    journal.save(journal_path)?;
+    Ok(journal.apply())
 }
 
-pub fn save_culling_progress(photo_dir: &Path, photos: &Vec<ImageInfo>) -> io::Result<()> {
+pub fn save_culling_progress(
+    photo_dir: &Path,
+    photos: &[ImageInfo],
+    settings: &FolderSettings,
+) -> io::Result<()> {
     // This handles the initial opening case
     if photos.is_empty() {
         return Ok(());
     }
+    storage::save(photo_dir, photos, settings)
+}
+
+/// Writes the undo history to `.blitz/history.ron`, next to the culling progress.
+pub fn save_history(photo_dir: &Path, history: &History) -> io::Result<()> {
     let mut blitz_dir = photo_dir.to_path_buf();
     blitz_dir.push(".blitz");
+    fs::create_dir_all(&blitz_dir)?;
+    blitz_dir.push("history.ron");
 
-    match fs::create_dir_all(blitz_dir.clone()) {
-        Ok(_dir) => {}
-        Err(_err) => {}
-    };
-
-    blitz_dir.push("storage.ron");
-
-    // Serialize and write
-    let ron_str = ron::ser::to_string_pretty(&photos, PrettyConfig::new())
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-    fs::write(blitz_dir, ron_str)?;
+    let ron_str =
+        ron::ser::to_string_pretty(history, PrettyConfig::new()).map_err(io::Error::other)?;
+    storage::write_atomically(&blitz_dir, ron_str.as_bytes())
+}
 
-    Ok(())
+/// Reads the undo history of `photo_dir`, a missing or unreadable file starts a fresh one.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_history(photo_dir: &Path) -> History {
+    let mut history_path = photo_dir.to_path_buf();
+    history_path.push(".blitz");
+    history_path.push("history.ron");
+
+    fs::read(history_path)
+        .ok()
+        .and_then(|serialized_ron| ron::de::from_bytes(&serialized_ron).ok())
+        .unwrap_or_default()
 }
 
 #[allow(clippy::vec_init_then_push)]
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::app::test_dir::TestDir;
 
     #[test]
     fn test_commit_culling() {
@@ -144,6 +213,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "1.jpg".to_string(),
             data: [].into(), // Added field
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("tmp/2.jpg"),
@@ -152,6 +222,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "2.jpg".to_string(),
             data: [].into(), // Added field
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("tmp/3.jpg"),
@@ -160,6 +231,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "3.jpg".to_string(),
             data: [].into(), // Added field
+            ..Default::default()
         });
 
         let temp_path = PathBuf::from("tmp");
@@ -169,7 +241,17 @@ mod tests {
         fs::create_dir_all(&chaffe_path).unwrap();
         fs::create_dir_all(&wheat_path).unwrap();
 
-        commit_culling(&test_photos, &chaffe_path, &wheat_path);
+        let journal_path = CommitJournal::path(&temp_path);
+        let options = CommitOptions {
+            chaffe_dir: chaffe_path.clone(),
+            wheat_dir: wheat_path.clone(),
+            ..Default::default()
+        };
+        let journal = CommitJournal::plan(&test_photos, &options);
+        let failures = commit_culling(&journal, &journal_path);
+
+        assert_eq!(Some(0), failures.map(|failures| failures.len()).ok());
+        assert_eq!(2, CommitJournal::load(&journal_path).unwrap().entries.len());
 
         // Confirm first image was moved to chaffe folder and no longer exists in original folder
         assert_identical_files("assets/samples/1.jpg", "tmp/chaffe/1.jpg");
@@ -189,6 +271,88 @@ mod tests {
         fs::remove_dir_all(&temp_path).unwrap();
     }
 
+    #[test]
+    fn test_commit_culling_moves_raw_companion() {
+        let photo_dir = TestDir::new("commit_raw");
+        let wheat_path = photo_dir.join("wheat");
+        let chaffe_path = photo_dir.join("chaffe");
+        fs::create_dir_all(&wheat_path).unwrap();
+        fs::create_dir_all(&chaffe_path).unwrap();
+
+        fs::copy("assets/samples/1.jpg", photo_dir.join("DSC0001.JPG")).unwrap();
+        fs::write(photo_dir.join("DSC0001.arw"), b"raw").unwrap();
+
+        let test_photos = vec![ImageInfo {
+            path_processed: photo_dir.join("DSC0001.JPG"),
+            path_raw: Some(photo_dir.join("DSC0001.arw")),
+            rating: Rating::Approve,
+            texture: Arc::new(Mutex::new(None)),
+            image_name: "DSC0001.JPG".to_string(),
+            data: [].into(),
+            ..Default::default()
+        }];
+
+        let journal_path = CommitJournal::path(&photo_dir);
+        let options = CommitOptions {
+            chaffe_dir: chaffe_path.clone(),
+            wheat_dir: wheat_path.clone(),
+            ..Default::default()
+        };
+        let journal = CommitJournal::plan(&test_photos, &options);
+        let failures = commit_culling(&journal, &journal_path);
+
+        assert!(failures.unwrap().is_empty());
+        assert!(wheat_path.join("DSC0001.JPG").exists());
+        assert_eq!(
+            b"raw".to_vec(),
+            fs::read(wheat_path.join("DSC0001.arw")).unwrap()
+        );
+        assert!(!photo_dir.join("DSC0001.arw").exists());
+    }
+
+    #[test]
+    fn test_storage_without_stars_and_labels_still_loads() {
+        let old_storage = r#"[
+            (
+                path_processed: "/shoot/1.jpg",
+                path_raw: None,
+                rating: Approve,
+                image_name: "1.jpg",
+            ),
+        ]"#;
+        let photos: Vec<ImageInfo> = ron::de::from_str(old_storage).unwrap();
+
+        assert_eq!(Rating::Approve, photos[0].rating);
+        assert_eq!(0, photos[0].stars);
+        assert_eq!(None, photos[0].label);
+        assert_eq!(0, photos[0].rotation);
+    }
+
+    #[test]
+    fn test_history_survives_restart() {
+        let photo_dir = TestDir::new("history");
+
+        let mut history = History::default();
+        history.record(Some(history::Command::SetRating {
+            path: PathBuf::from("/shoot/1.jpg"),
+            before: Rating::Unrated,
+            after: Rating::Remove,
+        }));
+        save_history(&photo_dir, &history).unwrap();
+
+        let mut photos = vec![ImageInfo {
+            path_processed: PathBuf::from("/shoot/1.jpg"),
+            rating: Rating::Remove,
+            ..Default::default()
+        }];
+        let mut loaded_history = load_history(&photo_dir);
+        assert_eq!(
+            Some(0),
+            loaded_history.undo(&mut photos, &egui::Context::default())
+        );
+        assert_eq!(Rating::Unrated, photos[0].rating);
+    }
+
     fn copy_test_images_to_dir() {
         fs::copy(
             PathBuf::from("assets/samples/1.jpg"),
@@ -0,0 +1,63 @@
+//! Identifies a photo by its content instead of its path, so ratings stay attached when the
+//! folder is renamed, moved or mounted somewhere else.
+
+use sha2::{Digest, Sha256};
+
+/// How much of the start and end of a file goes into its fingerprint. Hashing whole RAW files
+/// would make opening a card slow, and the start already holds the EXIF timestamps.
+pub const SAMPLE_LEN: usize = 64 * 1024;
+
+/// A hash of the file size and its first and last 64 KiB, hex encoded.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub fn content_fingerprint(data: &[u8]) -> String {
+    sampled_fingerprint(
+        data.len() as u64,
+        data,
+        &data[data.len().saturating_sub(SAMPLE_LEN)..],
+    )
+}
+
+/// [`content_fingerprint`] of a file of `len` bytes that starts with `start` and ends with
+/// `end`, for files that aren't read whole.
+pub fn sampled_fingerprint(len: u64, start: &[u8], end: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(len.to_le_bytes());
+    hasher.update(&start[..start.len().min(SAMPLE_LEN)]);
+    hasher.update(&end[end.len().saturating_sub(SAMPLE_LEN)..]);
+    hasher.finalize()[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_changes_with_content() {
+        let photo = vec![7u8; 3 * SAMPLE_LEN];
+        let mut edited_start = photo.clone();
+        edited_start[10] = 8;
+        let mut edited_end = photo.clone();
+        edited_end[3 * SAMPLE_LEN - 1] = 8;
+
+        assert_eq!(32, content_fingerprint(&photo).len());
+        assert_eq!(
+            content_fingerprint(&photo),
+            content_fingerprint(&photo.clone())
+        );
+        assert_ne!(
+            content_fingerprint(&photo),
+            content_fingerprint(&edited_start)
+        );
+        assert_ne!(
+            content_fingerprint(&photo),
+            content_fingerprint(&edited_end)
+        );
+        assert_ne!(
+            content_fingerprint(&photo),
+            content_fingerprint(&photo[..photo.len() - 1])
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Undo/redo for the changes made while culling. Commands point at photos by path instead
+//! of index, so a history loaded from disk still finds its photos after a rescan.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::models::{ColorLabel, ImageInfo, Rating};
+
+/// Older steps are dropped, nobody undoes a thousand key presses.
+const MAX_HISTORY_LEN: usize = 1_000;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub enum Command {
+    SetRating {
+        path: PathBuf,
+        before: Rating,
+        after: Rating,
+    },
+    SetStars {
+        path: PathBuf,
+        before: u8,
+        after: u8,
+    },
+    SetLabel {
+        path: PathBuf,
+        before: Option<ColorLabel>,
+        after: Option<ColorLabel>,
+    },
+    Rotate {
+        path: PathBuf,
+        before: u8,
+        after: u8,
+    },
+    Navigate {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Changes made by a single key press, e.g. rating a photo and moving on to the next one.
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    /// Wraps the given changes into one step, `None` if nothing changed.
+    pub fn from_changes(changes: impl IntoIterator<Item = Option<Command>>) -> Option<Command> {
+        let mut changes: Vec<Command> = changes
+            .into_iter()
+            .flatten()
+            .filter(|change| !matches!(change, Command::Batch(batch) if batch.is_empty()))
+            .collect();
+        match changes.len() {
+            0 => None,
+            1 => changes.pop(),
+            _ => Some(Command::Batch(changes)),
+        }
+    }
+
+    /// Applies the command forwards (redo) or backwards (undo) and returns the index of the
+    /// photo that should be on screen afterwards.
+    fn apply(
+        &self,
+        photos: &mut [ImageInfo],
+        ctx: &egui::Context,
+        forwards: bool,
+    ) -> Option<usize> {
+        match self {
+            Command::SetRating {
+                path,
+                before,
+                after,
+            } => {
+                let index = find_photo(photos, path)?;
+                photos[index].rating = pick(forwards, before, after).clone();
+                Some(index)
+            }
+            Command::SetStars {
+                path,
+                before,
+                after,
+            } => {
+                let index = find_photo(photos, path)?;
+                photos[index].stars = *pick(forwards, before, after);
+                Some(index)
+            }
+            Command::SetLabel {
+                path,
+                before,
+                after,
+            } => {
+                let index = find_photo(photos, path)?;
+                photos[index].label = *pick(forwards, before, after);
+                Some(index)
+            }
+            Command::Rotate {
+                path,
+                before,
+                after,
+            } => {
+                let index = find_photo(photos, path)?;
+                let photo = &mut photos[index];
+                photo.forget_images(ctx);
+                photo.rotation = *pick(forwards, before, after);
+                photo.texture = Arc::new(Mutex::new(None));
+                Some(index)
+            }
+            Command::Navigate { from, to } => find_photo(photos, pick(forwards, from, to)),
+            Command::Batch(commands) => {
+                // Undo runs backwards, so we end up where the key press started and redo where it ended
+                let mut shown_index = None;
+                if forwards {
+                    for command in commands {
+                        shown_index = command.apply(photos, ctx, forwards).or(shown_index);
+                    }
+                } else {
+                    for command in commands.iter().rev() {
+                        shown_index = command.apply(photos, ctx, forwards).or(shown_index);
+                    }
+                }
+                shown_index
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default, Debug)]
+pub struct History {
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+    /// Counts every recorded, undone or redone step, so changes can be noticed cheaply.
+    #[serde(skip)]
+    revision: u64,
+}
+
+impl History {
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn record(&mut self, command: Option<Command>) {
+        let Some(command) = command else {
+            return;
+        };
+        self.revision += 1;
+        self.undo_stack.push(command);
+        if self.undo_stack.len() > MAX_HISTORY_LEN {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Reverts the last step, returns the index of the photo it touched.
+    pub fn undo(&mut self, photos: &mut [ImageInfo], ctx: &egui::Context) -> Option<usize> {
+        let command = self.undo_stack.pop()?;
+        self.revision += 1;
+        let shown_index = command.apply(photos, ctx, false);
+        self.redo_stack.push(command);
+        shown_index
+    }
+
+    /// Reapplies the last undone step, returns the index of the photo it touched.
+    pub fn redo(&mut self, photos: &mut [ImageInfo], ctx: &egui::Context) -> Option<usize> {
+        let command = self.redo_stack.pop()?;
+        self.revision += 1;
+        let shown_index = command.apply(photos, ctx, true);
+        self.undo_stack.push(command);
+        shown_index
+    }
+}
+
+fn pick<T>(forwards: bool, before: T, after: T) -> T {
+    if forwards {
+        after
+    } else {
+        before
+    }
+}
+
+fn find_photo(photos: &[ImageInfo], path: &Path) -> Option<usize> {
+    photos.iter().position(|photo| photo.path_processed == path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn photo(name: &str) -> ImageInfo {
+        ImageInfo {
+            path_processed: PathBuf::from(name),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_rating_and_navigation() {
+        let mut photos = vec![photo("1.jpg"), photo("2.jpg"), photo("3.jpg")];
+        let mut history = History::default();
+        let ctx = egui::Context::default();
+
+        photos[0].rating = Rating::Remove;
+        history.record(Command::from_changes([
+            Some(Command::SetRating {
+                path: PathBuf::from("1.jpg"),
+                before: Rating::Unrated,
+                after: Rating::Remove,
+            }),
+            Some(Command::Navigate {
+                from: PathBuf::from("1.jpg"),
+                to: PathBuf::from("2.jpg"),
+            }),
+        ]));
+
+        assert_eq!(Some(0), history.undo(&mut photos, &ctx));
+        assert_eq!(Rating::Unrated, photos[0].rating);
+        assert_eq!(None, history.undo(&mut photos, &ctx));
+
+        assert_eq!(Some(1), history.redo(&mut photos, &ctx));
+        assert_eq!(Rating::Remove, photos[0].rating);
+        assert_eq!(None, history.redo(&mut photos, &ctx));
+    }
+
+    #[test]
+    fn test_record_clears_redo() {
+        let mut photos = vec![photo("1.jpg")];
+        let mut history = History::default();
+        let ctx = egui::Context::default();
+        let stars = |before, after| {
+            Some(Command::SetStars {
+                path: PathBuf::from("1.jpg"),
+                before,
+                after,
+            })
+        };
+
+        history.record(stars(0, 3));
+        history.undo(&mut photos, &ctx);
+        history.record(stars(0, 5));
+
+        assert_eq!(None, history.redo(&mut photos, &ctx));
+        assert_eq!(Some(0), history.undo(&mut photos, &ctx));
+        assert_eq!(0, photos[0].stars);
+    }
+
+    #[test]
+    fn test_from_changes() {
+        assert_eq!(None, Command::from_changes([None, None]));
+        assert_eq!(
+            None,
+            Command::from_changes([Some(Command::Batch(Vec::new()))])
+        );
+        let navigate = Command::Navigate {
+            from: PathBuf::from("1.jpg"),
+            to: PathBuf::from("2.jpg"),
+        };
+        assert_eq!(
+            Some(navigate.clone()),
+            Command::from_changes([None, Some(navigate)])
+        );
+    }
+}
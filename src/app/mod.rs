@@ -1,12 +1,31 @@
 use std::{
+    collections::HashSet,
     path::PathBuf,
     sync::{Arc, Mutex, RwLock},
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashMap;
+
+#[cfg(not(target_arch = "wasm32"))]
+use autosave::Autosaver;
+use checksum::VerifyReport;
+use collision_policy::CollisionPolicy;
+use commit_journal::CommitFailure;
+use commit_mode::CommitMode;
+use commit_preview::CommitPreview;
+#[cfg(not(target_arch = "wasm32"))]
+use disk_cache::DiskCache;
 use egui::Key;
-use file_operations::save_culling_progress;
+use history::History;
 use log::{log, Level};
-use models::{ImageInfo, Rating};
+use models::{ColorLabel, ImageInfo, Rating, MAX_STARS};
+use navigation::Direction;
+#[cfg(not(target_arch = "wasm32"))]
+use prefetch::Prefetcher;
+use texture_cache::TextureCache;
+#[cfg(not(target_arch = "wasm32"))]
+use xmp::SidecarRating;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -24,6 +43,64 @@ pub struct BlitzApp {
     #[serde(skip)]
     pub chaffe_dir_target: Option<PathBuf>,
     pub max_texture_count: usize,
+    pub commit_mode: CommitMode,
+    pub collision_policy: CollisionPolicy,
+    /// Sends removed photos to the desktop trash instead of the chaffe folder.
+    pub trash_rejects: bool,
+    /// Hashes committed files before and after the transfer and keeps a manifest in the wheat folder.
+    pub verify_checksums: bool,
+    /// Keeps ratings and labels in XMP sidecars next to the photos as well, see `xmp`.
+    pub write_xmp_sidecars: bool,
+    /// Where committed files go below the wheat and chaffe folders, see `destination_template`.
+    pub destination_template: String,
+    /// The template being edited, `Some` while the settings window is open.
+    #[serde(skip)]
+    pub template_draft: Option<String>,
+    pub show_info_panel: bool,
+    #[serde(skip)]
+    pub expanded_bursts: HashSet<usize>,
+    /// Lives in the photo folder next to `storage.ron` rather than in the app state.
+    #[serde(skip)]
+    pub history: History,
+    #[serde(skip)]
+    pub commit_failures: Vec<CommitFailure>,
+    #[serde(skip)]
+    pub commit_preview: Option<CommitPreview>,
+    #[serde(skip)]
+    pub verify_report: Option<VerifyReport>,
+    /// Why the culling progress of the folder couldn't be restored, until it's dismissed.
+    #[serde(skip)]
+    pub storage_warning: Option<String>,
+    /// Started on the first change, see `autosave`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub autosaver: Option<Autosaver>,
+    /// The history revision the last autosave was requested for.
+    #[serde(skip)]
+    pub autosaved_revision: u64,
+    #[serde(skip)]
+    pub texture_cache: TextureCache,
+    /// Shows the statistics of the texture cache in a corner.
+    #[serde(skip)]
+    pub show_cache_stats: bool,
+    /// What the sidecars of each photo hold as far as blitz knows, keyed by the processed file.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub sidecar_ratings: HashMap<PathBuf, SidecarRating>,
+    /// Which way the photos ahead of the current one lie, see `prefetch`.
+    #[serde(skip)]
+    pub navigation_direction: Direction,
+    /// Started on the first frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub prefetcher: Option<Prefetcher>,
+    /// The thumbnails and screen-fit images kept in `.blitz/cache`, see `disk_cache`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub disk_cache: Arc<DiskCache>,
+    /// How large `.blitz/cache` may grow, in MiB.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub disk_cache_limit_mb: u64,
 }
 
 impl BlitzApp {
@@ -34,14 +111,34 @@ impl BlitzApp {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
+        let app: BlitzApp = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
 
-        if let Some(storage) = cc.storage {
-            let persisted_state: BlitzApp =
-                eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-            return persisted_state;
+        // A texture is all a shown photo needs, its file bytes and pixels are dropped once it's
+        // uploaded and `max_texture_count` bounds what stays in memory
+        cc.egui_ctx
+            .options_mut(|options| options.reduce_texture_memory = true);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let load_workers = worker_pool::WorkerPool::spawn("load", worker_pool::LOAD_WORKERS);
+        // Added after the egui_extras loaders, so it gets asked first for rotated and scaled images
+        #[cfg(not(target_arch = "wasm32"))]
+        let tiered_image_loader =
+            tiers::TieredImageLoader::new(app.disk_cache.clone(), load_workers.clone());
+        #[cfg(target_arch = "wasm32")]
+        let tiered_image_loader = tiers::TieredImageLoader::default();
+        cc.egui_ctx.add_image_loader(Arc::new(tiered_image_loader));
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            cc.egui_ctx
+                .add_bytes_loader(Arc::new(photo_loader::PhotoLoader::new(load_workers)));
+            app.disk_cache
+                .set_limit(app.disk_cache_limit_mb * 1024 * 1024);
         }
 
-        Default::default()
+        app
     }
 }
 
@@ -50,8 +147,8 @@ impl eframe::App for BlitzApp {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, self);
 
-        if let Ok(photos) = self.photos.try_read() {
-            let _ = save_culling_progress(&self.photo_dir, &photos);
+        if let Some(photos) = self.photos.try_read().ok().map(|photos| photos.clone()) {
+            self.save_now(photos);
         }
     }
 
@@ -67,15 +164,65 @@ impl eframe::App for BlitzApp {
         self.update_right_panel(ctx);
 
         self.update_center_panel(ctx);
+
+        self.update_commit_failures_window(ctx);
+
+        self.update_verify_report_window(ctx);
+
+        self.update_storage_warning_window(ctx);
+
+        self.update_settings_window(ctx);
+
+        self.update_cache_stats_overlay(ctx);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.prefetch(ctx);
+
+        self.evict_textures(ctx);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.autosave_if_changed();
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+mod autosave;
+mod burst;
+mod checksum;
+mod collision_policy;
+mod commit_journal;
+mod commit_mode;
+mod commit_preview;
 mod context_menu;
+mod destination_template;
+#[cfg(not(target_arch = "wasm32"))]
+mod disk_cache;
+mod exif;
 mod file_operations;
+mod fingerprint;
+mod history;
 mod models;
 mod navigation;
 #[cfg(not(target_arch = "wasm32"))]
 mod open_folder_native;
 #[cfg(target_arch = "wasm32")]
 mod open_folder_wasm;
+mod orientation;
 mod panels;
+mod percent_encoding;
+#[cfg(not(target_arch = "wasm32"))]
+mod photo_loader;
+#[cfg(not(target_arch = "wasm32"))]
+mod prefetch;
+mod raw_pairing;
+mod raw_preview;
+mod storage;
+#[cfg(test)]
+mod test_dir;
+mod texture_cache;
+mod tiers;
+mod tiff;
+mod trash;
+#[cfg(not(target_arch = "wasm32"))]
+mod worker_pool;
+mod xmp;
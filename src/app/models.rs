@@ -1,29 +1,141 @@
 use std::{
+    collections::HashSet,
     path::PathBuf,
     sync::{Arc, Mutex},
 };
 
-use super::BlitzApp;
+use image::metadata::Orientation;
 
-#[derive(serde::Deserialize, serde::Serialize, Clone)]
+use super::{
+    collision_policy::CollisionPolicy,
+    commit_mode::CommitMode,
+    destination_template,
+    exif::ImageMetadata,
+    history::History,
+    orientation,
+    tiers::{self, Tier},
+    BlitzApp,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use super::{disk_cache, photo_loader};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
 pub struct ImageInfo {
+    /// The file we display. For RAW-only shots this is the RAW file itself and we show the
+    /// JPEG preview embedded in it.
     pub path_processed: PathBuf,
     pub path_raw: Option<PathBuf>,
+    /// The image when it's already in memory, as in the web version. Empty on the desktop,
+    /// where `photo_loader` reads the file when it's about to be shown.
     #[serde(skip)]
     pub data: Arc<[u8]>,
     pub rating: Rating,
     #[serde(skip)]
     pub texture: Arc<Mutex<Option<egui::TextureHandle>>>,
     pub image_name: String,
+    #[serde(skip)]
+    pub metadata: ImageMetadata,
+    /// Shared by all frames of one burst, see `burst::assign_bursts`.
+    #[serde(skip)]
+    pub burst_id: Option<usize>,
+    /// Clockwise quarter turns the user applied on top of the EXIF orientation, 0 to 3.
+    #[serde(default)]
+    pub rotation: u8,
+    /// Second pass score for keepers, 0 means not scored yet.
+    #[serde(default)]
+    pub stars: u8,
+    #[serde(default)]
+    pub label: Option<ColorLabel>,
+    /// See `fingerprint::content_fingerprint`, missing in folders saved by older versions.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+impl ImageInfo {
+    pub fn display_orientation(&self) -> Orientation {
+        orientation::display_orientation(self.metadata.orientation, self.rotation)
+    }
+
+    /// The URI egui loads the image from at the size of `tier`.
+    pub fn image_uri(&self, tier: Tier) -> String {
+        #[cfg(not(target_arch = "wasm32"))]
+        let uri = match self.data.is_empty() {
+            true => photo_loader::photo_uri(&self.path_processed),
+            false => format!("bytes://{}", self.image_name),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let uri = format!("bytes://{}", self.image_name);
+        orientation::oriented_uri(tiers::tier_uri(uri, tier), self.display_orientation())
+    }
+
+    /// What to hand `egui::Image`, loading of the bytes is left to egui unless they are in `data`.
+    pub fn image_source(&self, tier: Tier) -> egui::ImageSource<'static> {
+        match self.data.is_empty() {
+            true => egui::ImageSource::Uri(self.image_uri(tier).into()),
+            false => egui::ImageSource::Bytes {
+                uri: self.image_uri(tier).into(),
+                bytes: egui::load::Bytes::Shared(self.data.clone()),
+            },
+        }
+    }
+
+    /// Drops every tier decoded for the current orientation, e.g. before rotating.
+    pub fn forget_images(&self, ctx: &egui::Context) {
+        for tier in Tier::ALL {
+            ctx.forget_image(&self.image_uri(tier));
+        }
+    }
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq, Clone, Default)]
 pub enum Rating {
+    #[default]
     Unrated,
     Approve,
     Remove,
 }
 
+pub const MAX_STARS: u8 = 5;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl ColorLabel {
+    pub const ALL: [ColorLabel; 5] = [
+        ColorLabel::Red,
+        ColorLabel::Yellow,
+        ColorLabel::Green,
+        ColorLabel::Blue,
+        ColorLabel::Purple,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorLabel::Red => "Red",
+            ColorLabel::Yellow => "Yellow",
+            ColorLabel::Green => "Green",
+            ColorLabel::Blue => "Blue",
+            ColorLabel::Purple => "Purple",
+        }
+    }
+
+    pub fn color(&self) -> egui::Color32 {
+        match self {
+            ColorLabel::Red => egui::Color32::from_rgb(220, 50, 47),
+            ColorLabel::Yellow => egui::Color32::from_rgb(235, 200, 30),
+            ColorLabel::Green => egui::Color32::from_rgb(80, 180, 60),
+            ColorLabel::Blue => egui::Color32::from_rgb(40, 120, 220),
+            ColorLabel::Purple => egui::Color32::from_rgb(150, 80, 200),
+        }
+    }
+}
+
 impl Default for BlitzApp {
     fn default() -> Self {
         Self {
@@ -31,9 +143,37 @@ impl Default for BlitzApp {
             photos: Arc::new(Vec::new().into()),
             photo_dir: PathBuf::new(),
             max_texture_count: 200,
+            commit_mode: CommitMode::default(),
+            collision_policy: CollisionPolicy::default(),
+            trash_rejects: false,
+            verify_checksums: false,
+            write_xmp_sidecars: false,
+            destination_template: destination_template::DEFAULT_TEMPLATE.to_string(),
+            template_draft: None,
+            show_info_panel: false,
             uv_size: 1.0,
             wheat_dir_target: None,
             chaffe_dir_target: None,
+            expanded_bursts: HashSet::new(),
+            history: History::default(),
+            commit_failures: Vec::new(),
+            commit_preview: None,
+            verify_report: None,
+            storage_warning: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            autosaver: None,
+            autosaved_revision: 0,
+            texture_cache: Default::default(),
+            show_cache_stats: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            sidecar_ratings: Default::default(),
+            navigation_direction: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            prefetcher: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_cache: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_cache_limit_mb: disk_cache::DEFAULT_LIMIT_MB,
         }
     }
 }
@@ -1,5 +1,8 @@
 use super::*;
 
+use egui::{KeyboardShortcut, Modifiers};
+use history::Command;
+
 impl BlitzApp {
     pub fn handle_user_input(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         if ui.button("Open folder…").clicked() {
@@ -14,67 +17,288 @@ impl BlitzApp {
         }
 
         if ui.button("Commit choices").clicked() {
-            self.commit_choices(ui);
+            self.preview_commit();
         }
 
-        // #[cfg(not(target_arch = "wasm32"))]
-        // if ui.button("Load next textures").clicked() {
-        //     let mut photos = (&self.photos).to_owned();
-        //     let max_texture_count = (&self.max_texture_count).to_owned();
-        //     let thread_ctx = ui.ctx().clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let has_journal = commit_journal::CommitJournal::path(&self.photo_dir).exists();
+            if ui
+                .add_enabled(has_journal, egui::Button::new("Undo last commit"))
+                .clicked()
+            {
+                self.undo_last_commit(ui);
+            }
+        }
 
-        //     let _handler = thread::spawn(move || {
-        //         open_folder_native::load_all_textures_into_memory(&mut photos, thread_ctx, max_texture_count);
-        //     });
-        // }
+        // Typing into a text field isn't culling
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        // Ctrl+Z also matches with shift held, so redo has to be consumed first
+        let redo_shortcut = KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+        let undo_shortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+        if ctx.input_mut(|i| i.consume_shortcut(&redo_shortcut)) {
+            redo(self, ctx);
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&undo_shortcut)) {
+            undo(self, ctx);
+        }
 
         if ctx.input(|i| i.key_pressed(Key::D)) {
             log!(Level::Info, "D pressed");
-            go_to_next_picture(self);
+            let navigation = go_to_next_picture(self);
+            self.history.record(navigation);
+        }
+
+        if ctx.input(|i| i.key_pressed(Key::I)) {
+            self.show_info_panel = !self.show_info_panel;
         }
 
         if ctx.input(|i| i.key_pressed(Key::A)) {
-            go_to_previous_picture(self)
+            let navigation = go_to_previous_picture(self);
+            self.history.record(navigation);
         }
 
         if ctx.input(|i| i.key_pressed(Key::ArrowLeft)) {
-            let photos_index = self.photos_index;
-            self.photos.write().unwrap()[photos_index].rating = Rating::Remove;
-            self.photos.write().unwrap()[photos_index].texture = Arc::new(Mutex::new(None));
-            go_to_next_picture(self);
+            let rating = set_current_rating(self, Rating::Remove);
+            let navigation = go_to_next_picture(self);
+            self.history
+                .record(Command::from_changes([rating, navigation]));
         }
         if ctx.input(|i| i.key_pressed(Key::ArrowRight)) {
-            let photos_index = self.photos_index;
-            self.photos.write().unwrap()[photos_index].rating = Rating::Approve;
-            go_to_next_picture(self);
+            let rating = set_current_rating(self, Rating::Approve);
+            let navigation = go_to_next_picture(self);
+            self.history
+                .record(Command::from_changes([rating, navigation]));
+        }
+        if ctx.input(|i| i.key_pressed(Key::B)) {
+            if let Some(ratings) = keep_best_frame_of_burst(self) {
+                let navigation = go_to_next_picture(self);
+                self.history
+                    .record(Command::from_changes([Some(ratings), navigation]));
+            }
+        }
+
+        let star_keys = [
+            Key::Num0,
+            Key::Num1,
+            Key::Num2,
+            Key::Num3,
+            Key::Num4,
+            Key::Num5,
+        ];
+        for (stars, key) in star_keys.into_iter().enumerate() {
+            if ctx.input(|i| i.key_pressed(key)) {
+                let command = set_current_stars(self, stars as u8);
+                self.history.record(command);
+            }
+        }
+
+        let label_keys = [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5];
+        for (key, label) in label_keys.into_iter().zip(ColorLabel::ALL) {
+            if ctx.input(|i| i.key_pressed(key)) {
+                let command = toggle_current_label(self, label);
+                self.history.record(command);
+            }
+        }
+
+        if ctx.input(|i| i.key_pressed(Key::Q)) {
+            let command = rotate_current_picture(self, ctx, 3);
+            self.history.record(command);
+        }
+        if ctx.input(|i| i.key_pressed(Key::E)) {
+            let command = rotate_current_picture(self, ctx, 1);
+            self.history.record(command);
         }
     }
 }
 
-pub fn go_to_next_picture(template_app: &mut BlitzApp) {
-    log::info!("Go to next picture");
-    if let Ok(photos) = template_app.photos.try_read() {
-        match get_next_picture_index(template_app.photos_index, &photos) {
-            Some(index) => {
-                log::info!("Moving to index: {}", index);
-                template_app.photos_index = index;
-            }
-            None => todo!("we rated everything so now we die"),
+/// Which way the user last moved through the photos.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Forward,
+    Backward,
+}
+
+impl BlitzApp {
+    /// Thumbnails set `photos_index` directly when clicked, this records the jump afterwards.
+    pub fn record_jump_from(&mut self, previous_index: usize) {
+        if let Ok(photos) = self.photos.try_read() {
+            let navigation = navigation_command(&photos, previous_index, self.photos_index);
+            self.history.record(navigation);
         }
     }
 }
 
-pub fn go_to_previous_picture(template_app: &mut BlitzApp) {
-    if let Ok(photos) = template_app.photos.try_read() {
-        match get_previous_picture_index(template_app.photos_index, &photos) {
-            Some(index) => template_app.photos_index = index,
-            None => todo!("we rated everything so now we die"),
+pub fn undo(template_app: &mut BlitzApp, ctx: &egui::Context) {
+    let mut photos = template_app.photos.write().unwrap();
+    if let Some(index) = template_app.history.undo(&mut photos, ctx) {
+        template_app.photos_index = index;
+    }
+}
+
+pub fn redo(template_app: &mut BlitzApp, ctx: &egui::Context) {
+    let mut photos = template_app.photos.write().unwrap();
+    if let Some(index) = template_app.history.redo(&mut photos, ctx) {
+        template_app.photos_index = index;
+    }
+}
+
+/// Rates the picture on screen, returns the change for the undo history.
+pub fn set_current_rating(template_app: &mut BlitzApp, rating: Rating) -> Option<Command> {
+    let photos_index = template_app.photos_index;
+    let mut photos = template_app.photos.write().unwrap();
+    let photo = photos.get_mut(photos_index)?;
+    if photo.rating == rating {
+        return None;
+    }
+    if rating == Rating::Remove {
+        photo.texture = Arc::new(Mutex::new(None));
+    }
+    let before = std::mem::replace(&mut photo.rating, rating.clone());
+    Some(Command::SetRating {
+        path: photo.path_processed.clone(),
+        before,
+        after: rating,
+    })
+}
+
+pub fn set_current_stars(template_app: &mut BlitzApp, stars: u8) -> Option<Command> {
+    let photos_index = template_app.photos_index;
+    let mut photos = template_app.photos.write().unwrap();
+    let photo = photos.get_mut(photos_index)?;
+    let stars = stars.min(MAX_STARS);
+    if photo.stars == stars {
+        return None;
+    }
+    let before = std::mem::replace(&mut photo.stars, stars);
+    Some(Command::SetStars {
+        path: photo.path_processed.clone(),
+        before,
+        after: stars,
+    })
+}
+
+/// Applies `label` to the picture on screen, or removes it if it already has that label.
+pub fn toggle_current_label(template_app: &mut BlitzApp, label: ColorLabel) -> Option<Command> {
+    let photos_index = template_app.photos_index;
+    let mut photos = template_app.photos.write().unwrap();
+    let photo = photos.get_mut(photos_index)?;
+    let after = match photo.label {
+        Some(current) if current == label => None,
+        _ => Some(label),
+    };
+    let before = std::mem::replace(&mut photo.label, after);
+    Some(Command::SetLabel {
+        path: photo.path_processed.clone(),
+        before,
+        after,
+    })
+}
+
+/// Rotates the picture on screen by `quarter_turns` clockwise. Only the rotation stored in
+/// `storage.ron` changes, the file itself is never touched.
+pub fn rotate_current_picture(
+    template_app: &mut BlitzApp,
+    ctx: &egui::Context,
+    quarter_turns: u8,
+) -> Option<Command> {
+    let photos_index = template_app.photos_index;
+    let mut photos = template_app.photos.write().unwrap();
+    let photo = photos.get_mut(photos_index)?;
+
+    // The image decoded for the previous orientation is never shown again
+    photo.forget_images(ctx);
+    let before = photo.rotation;
+    photo.rotation = (photo.rotation + quarter_turns) % 4;
+    photo.texture = Arc::new(Mutex::new(None));
+    Some(Command::Rotate {
+        path: photo.path_processed.clone(),
+        before,
+        after: photo.rotation,
+    })
+}
+
+/// Approves the frame on screen and rejects the other unrated frames of its burst.
+/// Returns `None` when the current picture isn't part of a burst.
+pub fn keep_best_frame_of_burst(template_app: &mut BlitzApp) -> Option<Command> {
+    let photos_index = template_app.photos_index;
+    let mut photos = template_app.photos.write().unwrap();
+    let Some(burst_id) = photos.get(photos_index).and_then(|photo| photo.burst_id) else {
+        log::info!("Picture {} isn't part of a burst", photos_index);
+        return None;
+    };
+
+    let mut changes = Vec::new();
+    for (index, photo) in photos.iter_mut().enumerate() {
+        if photo.burst_id != Some(burst_id) {
+            continue;
         }
+        let after = if index == photos_index {
+            Rating::Approve
+        } else if photo.rating == Rating::Unrated {
+            photo.texture = Arc::new(Mutex::new(None));
+            Rating::Remove
+        } else {
+            continue;
+        };
+        if photo.rating == after {
+            continue;
+        }
+        let before = std::mem::replace(&mut photo.rating, after.clone());
+        changes.push(Some(Command::SetRating {
+            path: photo.path_processed.clone(),
+            before,
+            after,
+        }));
+    }
+    // A burst with nothing left to rate is an empty batch, B still moves on from it
+    Some(Command::from_changes(changes).unwrap_or(Command::Batch(Vec::new())))
+}
+
+/// Moves to the next unrated picture and returns the jump for the undo history. Stays put
+/// once everything is rated, so the last rating can still be undone.
+pub fn go_to_next_picture(template_app: &mut BlitzApp) -> Option<Command> {
+    log::info!("Go to next picture");
+    let photos = template_app.photos.try_read().ok()?;
+    let Some(index) = get_next_picture_index(template_app.photos_index, &photos) else {
+        log::info!("Every picture is rated");
+        return None;
+    };
+    log::info!("Moving to index: {}", index);
+    let navigation = navigation_command(&photos, template_app.photos_index, index);
+    template_app.photos_index = index;
+    template_app.navigation_direction = Direction::Forward;
+    navigation
+}
+
+pub fn go_to_previous_picture(template_app: &mut BlitzApp) -> Option<Command> {
+    let photos = template_app.photos.try_read().ok()?;
+    let Some(index) = get_previous_picture_index(template_app.photos_index, &photos) else {
+        log::info!("Every picture is rated");
+        return None;
+    };
+    let navigation = navigation_command(&photos, template_app.photos_index, index);
+    template_app.photos_index = index;
+    template_app.navigation_direction = Direction::Backward;
+    navigation
+}
+
+/// The undo step for jumping from one picture to another, `None` if we didn't move.
+pub fn navigation_command(photos: &[ImageInfo], from: usize, to: usize) -> Option<Command> {
+    if from == to {
+        return None;
     }
+    Some(Command::Navigate {
+        from: photos.get(from)?.path_processed.clone(),
+        to: photos.get(to)?.path_processed.clone(),
+    })
 }
 
 pub fn get_next_picture_index(starting_index: usize, photos: &[ImageInfo]) -> Option<usize> {
-    log::info!("Get next picture index");
     let mut candidate_index = starting_index;
     loop {
         candidate_index += 1;
@@ -112,6 +336,113 @@ pub fn get_previous_picture_index(starting_index: usize, photos: &[ImageInfo]) -
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_shortcuts_ignored_while_typing() {
+        let mut app = BlitzApp {
+            photos: Arc::new(RwLock::new(vec![
+                ImageInfo::default(),
+                ImageInfo::default(),
+            ])),
+            ..Default::default()
+        };
+        let ctx = egui::Context::default();
+        let mut text = String::new();
+        let mut run_frame = |app: &mut BlitzApp, focus: bool, events: Vec<egui::Event>| {
+            let input = egui::RawInput {
+                events,
+                ..Default::default()
+            };
+            let _ = ctx.run(input, |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let response = ui.text_edit_singleline(&mut text);
+                    if focus {
+                        response.request_focus();
+                    } else {
+                        response.surrender_focus();
+                    }
+                    app.handle_user_input(ctx, ui);
+                });
+            });
+        };
+        let press_d = || {
+            vec![egui::Event::Key {
+                key: Key::D,
+                physical_key: None,
+                pressed: true,
+                repeat: false,
+                modifiers: Modifiers::NONE,
+            }]
+        };
+
+        run_frame(&mut app, true, Vec::new());
+        run_frame(&mut app, true, press_d());
+        assert_eq!(0, app.photos_index);
+
+        run_frame(&mut app, false, Vec::new());
+        run_frame(&mut app, false, press_d());
+        assert_eq!(1, app.photos_index);
+    }
+
+    #[test]
+    fn test_keep_best_frame_of_burst() {
+        let frame = |burst_id: Option<usize>, rating: Rating| ImageInfo {
+            rating,
+            burst_id,
+            ..Default::default()
+        };
+        let mut app = BlitzApp {
+            photos_index: 1,
+            photos: Arc::new(RwLock::new(vec![
+                frame(Some(0), Rating::Unrated),
+                frame(Some(0), Rating::Unrated),
+                frame(Some(0), Rating::Approve),
+                frame(None, Rating::Unrated),
+                frame(Some(1), Rating::Unrated),
+            ])),
+            ..Default::default()
+        };
+
+        assert!(keep_best_frame_of_burst(&mut app).is_some());
+        let ratings: Vec<Rating> = app
+            .photos
+            .read()
+            .unwrap()
+            .iter()
+            .map(|p| p.rating.clone())
+            .collect();
+        assert_eq!(
+            vec![
+                Rating::Remove,
+                Rating::Approve,
+                Rating::Approve,
+                Rating::Unrated,
+                Rating::Unrated
+            ],
+            ratings
+        );
+
+        app.photos_index = 3;
+        assert_eq!(None, keep_best_frame_of_burst(&mut app));
+    }
+
+    #[test]
+    fn test_stars_and_labels() {
+        let mut app = BlitzApp {
+            photos: Arc::new(RwLock::new(vec![ImageInfo::default()])),
+            ..Default::default()
+        };
+
+        set_current_stars(&mut app, 4);
+        toggle_current_label(&mut app, ColorLabel::Green);
+        assert_eq!(4, app.photos.read().unwrap()[0].stars);
+        assert_eq!(Some(ColorLabel::Green), app.photos.read().unwrap()[0].label);
+
+        toggle_current_label(&mut app, ColorLabel::Red);
+        assert_eq!(Some(ColorLabel::Red), app.photos.read().unwrap()[0].label);
+        toggle_current_label(&mut app, ColorLabel::Red);
+        assert_eq!(None, app.photos.read().unwrap()[0].label);
+    }
+
     #[test]
     fn test_get_next_picture_index_no_ratings() {
         let mut test_photos = Vec::new();
@@ -122,6 +453,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -130,6 +462,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -138,6 +471,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
 
         let next_picture_index = get_next_picture_index(0, &test_photos);
@@ -165,6 +499,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -173,6 +508,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -181,6 +517,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
 
         let next_picture_index = get_next_picture_index(0, &test_photos);
@@ -208,6 +545,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -216,6 +554,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -224,6 +563,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
         test_photos.push(ImageInfo {
             path_processed: PathBuf::from("/tmp/DSC55555.jpg"),
@@ -232,6 +572,7 @@ mod tests {
             texture: Arc::new(Mutex::new(None)),
             image_name: "/tmp/DSC55555.jpg".to_string(),
             data: [].into(),
+            ..Default::default()
         });
 
         let next_picture_index = get_next_picture_index(0, &test_photos);
@@ -1,13 +1,12 @@
 use std::{
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     fs::{self},
     path::Path,
     path::PathBuf,
-    sync::{Arc, Mutex, RwLock},
+    sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
-use egui::{ColorImage, TextureHandle};
-
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 #[cfg(target_arch = "wasm32")]
@@ -21,68 +20,182 @@ use web_sys::{
     FileSystemHandle, FileSystemHandleKind,
 };
 
-use super::{BlitzApp, ImageInfo, Rating};
+use super::{
+    burst, exif,
+    file_operations::{get_chaffe_dir, get_wheat_dir, load_history},
+    photo_loader::{read_head, FileHead},
+    raw_pairing::{is_raw_extension, RawCompanions},
+    storage,
+    xmp::{self, SidecarRating},
+    BlitzApp, ImageInfo, Rating,
+};
 
 impl BlitzApp {
     // open folder handles initialization of the app and the loading of the images
     #[cfg(not(target_arch = "wasm32"))]
     pub fn open_folder_action(&mut self, ui: &mut egui::Ui, path: PathBuf) {
+        // Changes to the previous folder since the last frame still go to its `.blitz` folder
+        self.autosave_if_changed();
         self.photo_dir = path.clone();
+        self.history = load_history(&self.photo_dir);
+        // The loaded history is on disk already
+        self.autosaved_revision = self.history.revision();
+        // Nothing of the previous folder is shown again
+        ui.ctx().forget_all_images();
+        self.texture_cache.clear();
 
-        // // Restore state from .blitz folder
-        let mut blitz_dir = self.photo_dir.clone();
-        blitz_dir.push(".blitz");
-        blitz_dir.push("storage.ron");
-
-        match fs::read(blitz_dir.clone()) {
-            Ok(seralized_ron) => match ron::de::from_bytes::<Vec<ImageInfo>>(&seralized_ron) {
-                Ok(stored_state) => {
-                    let mut photos: Vec<ImageInfo> = Vec::new();
-                    init_photos_state(&(self.photo_dir), &mut photos, Some(stored_state));
-                    self.photos_index = get_first_unrated_image_index(&photos);
-                    self.photos = Arc::new(photos.into());
-                }
-                Err(_) => todo!("failed to deserialized the previous state"),
-            },
-            Err(_) => {
-                if let Ok(mut photos) = self.photos.try_write() {
-                    self.photos_index = 0;
-                    init_photos_state(&(self.photo_dir), &mut photos, None);
-                }
-            }
-        }
-
-        let _photos = self.photos.to_owned();
-        let _max_texture_count = self.max_texture_count.to_owned();
-        let _thread_ctx = ui.ctx().clone();
+        // Restore state from .blitz folder
+        let envelope = match storage::load(&self.photo_dir) {
+            Ok(envelope) => envelope,
+            Err(err) => self.recover_storage(err),
+        };
+        // Targets of the previous folder don't carry over, a folder without any starts over
+        let (settings, stored_state) = match envelope {
+            Some(envelope) => (envelope.settings, Some(envelope.photos)),
+            None => (storage::FolderSettings::default(), None),
+        };
+        self.wheat_dir_target = settings.wheat_dir;
+        self.chaffe_dir_target = settings.chaffe_dir;
 
-        // let _handler = thread::spawn(move || {
-        //     if let Ok(photos) = photos.try_write() {
-        //         load_all_textures_into_memory(&mut photos, thread_ctx, max_texture_count);
-        //     }
+        // Committed photos aren't queued again, wherever the targets are
+        let excluded_dirs = [get_wheat_dir(self), get_chaffe_dir(self)];
+        let mut photos: Vec<ImageInfo> = Vec::new();
+        init_photos_state(&(self.photo_dir), &excluded_dirs, &mut photos, stored_state);
+        self.photos_index = get_first_unrated_image_index(&photos);
+        self.disk_cache.open(&self.photo_dir, &photos);
+        self.sidecar_ratings = photos
+            .iter()
+            .map(|photo| (photo.path_processed.clone(), SidecarRating::of(photo)))
+            .collect();
+        self.photos = Arc::new(photos.into());
+    }
 
-        // });
+    /// Sets an unreadable `storage.ron` aside and falls back to its newest readable backup.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recover_storage(&mut self, err: storage::StorageError) -> Option<storage::StorageEnvelope> {
+        log::warn!("Couldn't restore {:?}: {}", self.photo_dir, err);
+        let kept_as = match storage::back_up_unreadable(&self.photo_dir) {
+            Ok(backup_path) => format!(" It was kept as {}.", backup_path.display()),
+            Err(_) => String::new(),
+        };
+        let (restored, outcome) = match storage::load_newest_backup(&self.photo_dir) {
+            Some((backup_path, envelope)) => (
+                Some(envelope),
+                format!("The ratings of {} were restored.", backup_path.display()),
+            ),
+            None => (None, "The folder starts over unrated.".to_string()),
+        };
+        self.storage_warning = Some(format!("{}.{} {}", err, kept_as, outcome));
+        restored
     }
 }
 
+/// How many folder levels below the opened folder get scanned, e.g. `DCIM/100FUJI` is two.
+const MAX_SCAN_DEPTH: usize = 4;
+
+/// Where blitz keeps its state and cache, in the opened folder and in subfolders that were
+/// opened on their own before.
+const BLITZ_DIR_NAME: &str = ".blitz";
+
+/// Scans `photo_dir` for photos, skipping `excluded_dirs` and everything below them.
 #[cfg(not(target_arch = "wasm32"))]
 fn init_photos_state(
     photo_dir: &Path,
+    excluded_dirs: &[PathBuf],
     photos: &mut Vec<ImageInfo>,
     stored_photos: Option<Vec<ImageInfo>>,
 ) {
-    let paths = fs::read_dir(photo_dir).unwrap();
-    for path in paths {
-        let x = path.unwrap();
-        match x.path().is_file() {
-            false => {} // TODO: handle folders recursively?
-            true => {
-                if let Some(image_info) = init_image_info(x, &stored_photos) {
-                    photos.push(image_info);
-                }
-            }
+    let stored_at = fs::metadata(storage::path(photo_dir))
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let is_first_open = stored_photos.is_none();
+    // Resolved once, so a target spelled differently is still recognized
+    let excluded_dirs: Vec<PathBuf> = excluded_dirs
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .collect();
+    scan_dir(
+        photo_dir,
+        0,
+        &excluded_dirs,
+        photos,
+        &stored_photos,
+        stored_at,
+    );
+    if is_first_open {
+        import_protected_ratings(photos);
+    }
+    burst::assign_bursts(photos);
+}
+
+/// Adds the photos of `dir` and then recurses into its subfolders, so the photos of one
+/// subfolder always end up next to each other in `photos`.
+#[cfg(not(target_arch = "wasm32"))]
+fn scan_dir(
+    dir: &Path,
+    depth: usize,
+    excluded_dirs: &[PathBuf],
+    photos: &mut Vec<ImageInfo>,
+    stored_photos: &Option<Vec<ImageInfo>>,
+    stored_at: Option<SystemTime>,
+) {
+    let mut entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(err) => {
+            log::warn!("Couldn't read {:?}: {}", dir, err);
+            return;
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let (sub_dirs, files): (Vec<fs::DirEntry>, Vec<fs::DirEntry>) =
+        entries.into_iter().partition(|entry| entry.path().is_dir());
+
+    let file_paths: Vec<PathBuf> = files.iter().map(fs::DirEntry::path).collect();
+    let raw_companions = RawCompanions::from_paths(file_paths.iter().map(PathBuf::as_path));
+    // RAW files with a processed sibling ride along with it instead of getting their own entry
+    let paired_raws: Vec<PathBuf> = file_paths
+        .iter()
+        .filter(|path| path.extension().is_some_and(is_processed_extension))
+        .filter_map(|path| raw_companions.companion_for(path))
+        .collect();
+
+    for file in files {
+        if paired_raws.contains(&file.path()) {
+            continue;
+        }
+        if let Some(image_info) = init_image_info(file, &raw_companions, stored_photos, stored_at) {
+            photos.push(image_info);
+        }
+    }
+
+    if depth >= MAX_SCAN_DEPTH {
+        if !sub_dirs.is_empty() {
+            log::debug!("Not descending into subfolders of {:?}, too deep", dir);
         }
+        return;
     }
+
+    for sub_dir in sub_dirs {
+        if is_excluded_dir(&sub_dir.path(), excluded_dirs) {
+            continue;
+        }
+        scan_dir(
+            &sub_dir.path(),
+            depth + 1,
+            excluded_dirs,
+            photos,
+            stored_photos,
+            stored_at,
+        );
+    }
+}
+
+fn is_excluded_dir(dir: &Path, excluded_dirs: &[PathBuf]) -> bool {
+    if dir.file_name() == Some(OsStr::new(BLITZ_DIR_NAME)) {
+        return true;
+    }
+    fs::canonicalize(dir).is_ok_and(|dir| excluded_dirs.contains(&dir))
 }
 
 fn get_first_unrated_image_index(photos: &[ImageInfo]) -> usize {
@@ -110,26 +223,11 @@ fn number_of_unrated_images(photos: &[ImageInfo]) -> usize {
     counter
 }
 
-#[allow(dead_code)]
-pub fn load_all_textures_into_memory(
-    photos: &mut Vec<Arc<RwLock<ImageInfo>>>,
-    ctx: egui::Context,
-    max_texture_count: usize,
-) {
-    let mut texture_counter = 0;
-    for image_info in photos {
-        if image_info.read().unwrap().rating == Rating::Unrated
-            && texture_counter < max_texture_count
-            && load_texture_into_memory(image_info, ctx.clone()).is_some()
-        {
-            texture_counter += 1
-        }
-    }
-}
-
 fn init_image_info(
     dir_entry: fs::DirEntry,
+    raw_companions: &RawCompanions,
     stored_photos: &Option<Vec<ImageInfo>>,
+    stored_at: Option<SystemTime>,
 ) -> Option<ImageInfo> {
     let entry_path = dir_entry.path();
     let file_extension = match entry_path.extension() {
@@ -139,7 +237,7 @@ fn init_image_info(
             return None;
         }
     };
-    if !is_file_extension_supported(file_extension) {
+    if !is_file_extension_supported(file_extension.clone()) {
         return None;
     }
     let filename = dir_entry
@@ -149,12 +247,53 @@ fn init_image_info(
         .to_str()
         .unwrap()
         .to_string();
-    let data: Arc<[u8]> = match fs::read(dir_entry.path().clone()) {
-        Ok(result) => result.into(),
+    // The image itself is only read when it's about to be shown, see `photo_loader`
+    let FileHead { head, fingerprint } = match read_head(&entry_path) {
+        Ok(file_head) => file_head,
         Err(_) => return None, // If we can't read the image we just skip it
     };
 
-    let image_rating = get_rating_for_image(stored_photos, dir_entry.path().clone());
+    let metadata = exif::read_metadata(&head);
+    // A folder blitz has never saved starts from what the camera or other tools recorded
+    let embedded_rating = match stored_photos {
+        Some(_) => None,
+        None => xmp::embedded_rating(&head, &metadata),
+    };
+
+    // RAW-only shots are displayed through the JPEG preview embedded by the camera
+    let path_raw = match is_raw_extension(&file_extension) {
+        true => None,
+        false => raw_companions.companion_for(&dir_entry.path()),
+    };
+
+    let stored_image = find_stored_image(stored_photos, &dir_entry.path(), &fingerprint);
+    // Ratings changed in another app since blitz last saved the folder win
+    let sidecar_rating = [path_raw.as_deref(), Some(entry_path.as_path())]
+        .into_iter()
+        .flatten()
+        .find_map(xmp::read_sidecar)
+        .filter(|(sidecar_rating, modified)| match stored_image {
+            Some(stored_image) => {
+                SidecarRating::of(stored_image) != *sidecar_rating
+                    && stored_at.map_or(true, |stored_at| *modified > stored_at)
+            }
+            None => true,
+        })
+        .map(|(sidecar_rating, _)| sidecar_rating)
+        .or(match stored_photos {
+            Some(_) => None,
+            None => embedded_rating,
+        });
+    let (image_rating, stars) = match sidecar_rating {
+        Some(sidecar_rating) => sidecar_rating.verdict(),
+        None => stored_image.map_or_else(Default::default, |image| {
+            (image.rating.clone(), image.stars)
+        }),
+    };
+    let label = match sidecar_rating {
+        Some(sidecar_rating) => sidecar_rating.label,
+        None => stored_image.and_then(|image| image.label),
+    };
 
     log::info!(
         "Found match for {:?}. Rating: {:?}",
@@ -164,46 +303,60 @@ fn init_image_info(
 
     let image_info = ImageInfo {
         path_processed: dir_entry.path().clone(),
-        path_raw: get_raw_variant(&dir_entry.path()),
+        path_raw,
         rating: image_rating,
         texture: Arc::new(Mutex::new(None)),
         image_name: filename,
-        data,
+        data: Default::default(),
+        metadata,
+        burst_id: None,
+        rotation: stored_image.map_or(0, |image| image.rotation),
+        stars,
+        label,
+        fingerprint: Some(fingerprint),
     };
     Some(image_info)
 }
 
-fn load_texture_into_memory(
-    image_info: &mut Arc<RwLock<ImageInfo>>,
-    ctx: egui::Context,
-) -> Option<TextureHandle> {
-    let data = match fs::read(&image_info.read().unwrap().path_processed) {
-        Ok(result) => result,
-        Err(_) => return None, // If we can't read the image we just skip it
-    };
-    let image_data = create_image(&data);
-
-    let texture_handle = match image_data {
-        Ok(color_image) => {
-            let texture_id = image_info
-                .read()
-                .unwrap()
-                .path_processed
-                .clone()
-                .as_mut_os_str()
-                .to_str()
-                .unwrap()
-                .to_string();
-            Some(ctx.load_texture(texture_id, color_image, Default::default()))
+/// Makes the photos protected on the camera keepers, unless another rating was found for them.
+/// Cameras record the protection by making the file read-only, but so does copying a card
+/// from a read-only medium, so a folder that is mostly read-only doesn't count.
+fn import_protected_ratings(photos: &mut [ImageInfo]) {
+    let protected: Vec<usize> = photos
+        .iter()
+        .enumerate()
+        .filter(|(_, photo)| is_protected(photo))
+        .map(|(index, _)| index)
+        .collect();
+    if protected.len() * 2 > photos.len() {
+        log::info!("Most photos are read-only, not taking them for protected ones");
+        return;
+    }
+    for index in protected {
+        let photo = &mut photos[index];
+        let is_rated = photo.rating != Rating::Unrated || photo.stars > 0 || photo.label.is_some();
+        if !is_rated {
+            (photo.rating, photo.stars) = SidecarRating {
+                rating: 1,
+                label: None,
+            }
+            .verdict();
         }
-        Err(_) => return None,
-    };
+    }
+}
 
-    image_info.write().unwrap().texture = Arc::new(Mutex::new(texture_handle.clone()));
-    texture_handle
+fn is_protected(photo: &ImageInfo) -> bool {
+    [Some(&photo.path_processed), photo.path_raw.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|file| fs::metadata(file).is_ok_and(|metadata| metadata.permissions().readonly()))
 }
 
 fn is_file_extension_supported(extension: OsString) -> bool {
+    is_processed_extension(&extension) || is_raw_extension(&extension)
+}
+
+fn is_processed_extension(extension: &OsStr) -> bool {
     if extension == "JPG" {
         return true;
     }
@@ -213,49 +366,278 @@ fn is_file_extension_supported(extension: OsString) -> bool {
     false
 }
 
-fn get_raw_variant(processed_path: &Path) -> Option<PathBuf> {
-    let mut raw_path = processed_path.to_path_buf();
-    match raw_path.set_extension("RAF") {
-        true => Some(raw_path),
-        false => None,
-    }
+/// Finds the stored state of a photo by its content, so it's found again after the folder
+/// moved. Among identical copies the one with the same path or file name wins. Entries saved
+/// before fingerprints existed only match by path, as does a path whose content changed.
+fn find_stored_image<'a>(
+    stored_photos: &'a Option<Vec<ImageInfo>>,
+    image_path: &Path,
+    fingerprint: &str,
+) -> Option<&'a ImageInfo> {
+    let stored_photos = stored_photos.as_ref()?;
+    let same_content: Vec<&ImageInfo> = stored_photos
+        .iter()
+        .filter(|image| image.fingerprint.as_deref() == Some(fingerprint))
+        .collect();
+    let image = same_content
+        .iter()
+        .find(|image| image.path_processed == image_path)
+        .or_else(|| {
+            same_content
+                .iter()
+                .find(|image| image.path_processed.file_name() == image_path.file_name())
+        })
+        .or(same_content.first())
+        .copied()
+        .or_else(|| {
+            stored_photos
+                .iter()
+                .find(|image| image.fingerprint.is_none() && image.path_processed == image_path)
+        })?;
+    log::debug!(
+        "Found match for {:?}. Rating: {:?}",
+        image.path_processed,
+        image.rating
+    );
+    Some(image)
 }
 
-fn get_rating_for_image(stored_photos: &Option<Vec<ImageInfo>>, image_path: PathBuf) -> Rating {
-    match stored_photos {
-        Some(photos) => {
-            for image in photos {
-                if image.path_processed == image_path {
-                    log::debug!(
-                        "Found match for {:?}. Rating: {:?}",
-                        image.path_processed,
-                        image.rating.clone()
-                    );
-                    return image.rating.clone();
-                }
-            }
-            Rating::Unrated
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+    use crate::app::{
+        fingerprint::content_fingerprint, models::ColorLabel, orientation::create_image,
+        photo_loader::read_photo,
+    };
+
+    #[test]
+    fn test_init_photos_state_recursive() {
+        let photo_dir = TestDir::new("recursive_scan");
+
+        let sample_locations = [
+            "root.jpg",
+            "DCIM/100FUJI/first.JPG",
+            "DCIM/101FUJI/second.jpg",
+            "wheat/approved.jpg",
+            "picks/chaffe/removed.jpg",
+            ".blitz/cached.jpg",
+            "2023/wheat/shoot.jpg",
+            "2023/chaffe/shoot.jpg",
+            "1/2/3/4/deepest_allowed.jpg",
+            "1/2/3/4/5/too_deep.jpg",
+        ];
+        for location in sample_locations {
+            let destination = photo_dir.join(location);
+            fs::create_dir_all(destination.parent().unwrap()).unwrap();
+            fs::copy("assets/samples/1.jpg", destination).unwrap();
         }
-        None => Rating::Unrated,
+
+        // The targets are excluded however they are spelled, folders of the same name aren't
+        let excluded_dirs = [
+            photo_dir.join("wheat"),
+            photo_dir.join("picks/../picks/chaffe/"),
+        ];
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &excluded_dirs, &mut photos, None);
+        let names: Vec<&str> = photos.iter().map(|p| p.image_name.as_str()).collect();
+
+        assert_eq!(
+            vec![
+                "root.jpg",
+                "deepest_allowed.jpg",
+                "shoot.jpg",
+                "shoot.jpg",
+                "first.JPG",
+                "second.jpg"
+            ],
+            names
+        );
     }
-}
 
-fn create_image(image_data: &[u8]) -> Result<ColorImage, image::ImageError> {
-    let image = image::load_from_memory(image_data)?;
-    let size = [image.width() as _, image.height() as _];
-    let image_buffer = image.to_rgba8();
-    let pixels = image_buffer.as_flat_samples();
-    Ok(ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()))
-}
+    #[test]
+    fn test_ratings_are_read_from_sidecars() {
+        let photo_dir = TestDir::new("sidecar_ratings");
+        fs::copy("assets/samples/1.jpg", photo_dir.join("1.jpg")).unwrap();
+        fs::copy("assets/samples/2.jpg", photo_dir.join("2.jpg")).unwrap();
+        let sidecar_rating = SidecarRating {
+            rating: 3,
+            label: Some(ColorLabel::Red),
+        };
+        xmp::write_sidecar(&photo_dir.join("1.jpg"), sidecar_rating).unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, None);
+
+        assert_eq!(Rating::Approve, photos[0].rating);
+        assert_eq!(3, photos[0].stars);
+        assert_eq!(Some(ColorLabel::Red), photos[0].label);
+        assert_eq!(Rating::Unrated, photos[1].rating);
+    }
 
     #[test]
-    fn test_get_raw_variant() {
-        let path = PathBuf::from("/tmp/DSC55555.jpg");
-        let raw_variant = get_raw_variant(&path).unwrap();
-        assert_eq!("RAF", raw_variant.extension().unwrap())
+    fn test_first_open_imports_camera_ratings() {
+        let photo_dir = TestDir::new("import_ratings");
+
+        // An editor's rating in an XMP packet right after the start of image marker
+        let packet = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="4" xmp:Label="Green"/></rdf:RDF></x:xmpmeta>"#;
+        let mut segment = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        segment.extend_from_slice(packet);
+        let sample = fs::read("assets/samples/1.jpg").unwrap();
+        let mut embedded = sample[..2].to_vec();
+        embedded.extend_from_slice(&[0xFF, 0xE1]);
+        embedded.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+        embedded.extend_from_slice(&segment);
+        embedded.extend_from_slice(&sample[2..]);
+        fs::write(photo_dir.join("1.jpg"), embedded).unwrap();
+
+        // Protected on the camera
+        fs::copy("assets/samples/2.jpg", photo_dir.join("2.jpg")).unwrap();
+        let mut permissions = fs::metadata(photo_dir.join("2.jpg")).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(photo_dir.join("2.jpg"), permissions).unwrap();
+
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, None);
+        assert_eq!(Rating::Approve, photos[0].rating);
+        assert_eq!(4, photos[0].stars);
+        assert_eq!(Some(ColorLabel::Green), photos[0].label);
+        assert_eq!(Rating::Approve, photos[1].rating);
+
+        // Every photo read-only is a read-only copy, not a camera protecting all of them
+        let mut permissions = fs::metadata(photo_dir.join("1.jpg")).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(photo_dir.join("1.jpg"), permissions).unwrap();
+        fs::copy("assets/samples/3.jpg", photo_dir.join("3.jpg")).unwrap();
+        let mut permissions = fs::metadata(photo_dir.join("3.jpg")).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(photo_dir.join("3.jpg"), permissions).unwrap();
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, None);
+        assert_eq!(Rating::Approve, photos[0].rating);
+        assert_eq!(Rating::Unrated, photos[1].rating);
+        assert_eq!(Rating::Unrated, photos[2].rating);
+        fs::remove_file(photo_dir.join("3.jpg")).unwrap();
+
+        // Once blitz has saved the folder, its own ratings count
+        let stored_photos = vec![ImageInfo {
+            path_processed: photo_dir.join("2.jpg"),
+            ..Default::default()
+        }];
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, Some(stored_photos));
+        assert_eq!(Rating::Unrated, photos[0].rating);
+        assert_eq!(Rating::Unrated, photos[1].rating);
+    }
+
+    #[test]
+    fn test_stored_rating_and_rotation_are_restored() {
+        let photo_dir = TestDir::new("stored_state");
+        fs::copy("assets/samples/1.jpg", photo_dir.join("1.jpg")).unwrap();
+
+        let stored_photos = vec![ImageInfo {
+            path_processed: photo_dir.join("1.jpg"),
+            rating: Rating::Approve,
+            rotation: 3,
+            stars: 4,
+            label: Some(ColorLabel::Blue),
+            ..Default::default()
+        }];
+
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, Some(stored_photos));
+
+        assert_eq!(Rating::Approve, photos[0].rating);
+        assert_eq!(3, photos[0].rotation);
+        assert_eq!(4, photos[0].stars);
+        assert_eq!(Some(ColorLabel::Blue), photos[0].label);
+    }
+
+    #[test]
+    fn test_stored_state_follows_moved_folder() {
+        let photo_dir = TestDir::new("moved_folder");
+        fs::copy("assets/samples/1.jpg", photo_dir.join("renamed.jpg")).unwrap();
+        fs::copy("assets/samples/2.jpg", photo_dir.join("2.jpg")).unwrap();
+        let fingerprint = |name: &str| {
+            Some(content_fingerprint(
+                &fs::read(Path::new("assets/samples").join(name)).unwrap(),
+            ))
+        };
+
+        let stored_photos = vec![
+            ImageInfo {
+                path_processed: PathBuf::from("/media/old_card/1.jpg"),
+                rating: Rating::Approve,
+                stars: 5,
+                fingerprint: fingerprint("1.jpg"),
+                ..Default::default()
+            },
+            // Same path as before, but the file now holds another photo
+            ImageInfo {
+                path_processed: photo_dir.join("2.jpg"),
+                rating: Rating::Remove,
+                fingerprint: fingerprint("3.jpg"),
+                ..Default::default()
+            },
+        ];
+
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, Some(stored_photos));
+
+        assert_eq!(photo_dir.join("2.jpg"), photos[0].path_processed);
+        assert_eq!(Rating::Unrated, photos[0].rating);
+        assert_eq!(photo_dir.join("renamed.jpg"), photos[1].path_processed);
+        assert_eq!(Rating::Approve, photos[1].rating);
+        assert_eq!(5, photos[1].stars);
+        assert_eq!(fingerprint("1.jpg"), photos[1].fingerprint);
+    }
+
+    #[test]
+    fn test_raw_companions_are_paired() {
+        let photo_dir = TestDir::new("raw_pairing");
+
+        for name in ["DSC0001.jpg", "DSC0002.JPG", "DSC0003.jpg"] {
+            fs::copy("assets/samples/1.jpg", photo_dir.join(name)).unwrap();
+        }
+        for name in ["DSC0001.ARW", "DSC0002.nef"] {
+            fs::write(photo_dir.join(name), b"raw").unwrap();
+        }
+
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, None);
+        let raw_paths: Vec<Option<PathBuf>> = photos.iter().map(|p| p.path_raw.clone()).collect();
+
+        assert_eq!(
+            vec![
+                Some(photo_dir.join("DSC0001.ARW")),
+                Some(photo_dir.join("DSC0002.nef")),
+                None
+            ],
+            raw_paths
+        );
+    }
+
+    #[test]
+    fn test_raw_only_files_use_embedded_preview() {
+        let photo_dir = TestDir::new("raw_only");
+
+        let preview = fs::read("assets/samples/2.jpg").unwrap();
+        let mut raf = b"FUJIFILMCCD-RAW ".to_vec();
+        raf.resize(100, 0);
+        raf[84..88].copy_from_slice(&100u32.to_be_bytes());
+        raf[88..92].copy_from_slice(&(preview.len() as u32).to_be_bytes());
+        raf.extend_from_slice(&preview);
+        fs::write(photo_dir.join("DSCF0001.RAF"), &raf).unwrap();
+
+        let mut photos = Vec::new();
+        init_photos_state(&photo_dir, &[], &mut photos, None);
+
+        assert_eq!(1, photos.len());
+        assert_eq!(photo_dir.join("DSCF0001.RAF"), photos[0].path_processed);
+        assert_eq!(None, photos[0].path_raw);
+        assert!(photos[0].metadata.capture_time.is_some());
+        let data = read_photo(&photos[0].path_processed).unwrap();
+        assert_eq!(preview, data);
+        assert!(create_image(&data, photos[0].display_orientation()).is_ok());
     }
 }
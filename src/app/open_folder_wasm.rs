@@ -1,5 +1,6 @@
 use log;
 use std::{
+    ffi::OsStr,
     path::PathBuf,
     sync::{Arc, Mutex},
 };
@@ -11,7 +12,10 @@ use web_sys::{
     File, FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemHandle, FileSystemHandleKind,
 };
 
-use super::{BlitzApp, ImageInfo, Rating};
+use super::{
+    burst, exif, fingerprint::content_fingerprint, raw_pairing::is_raw_extension, raw_preview, xmp,
+    BlitzApp, ImageInfo,
+};
 
 pub struct ImageFile {
     pub data: Arc<[u8]>,
@@ -29,18 +33,29 @@ impl BlitzApp {
             });
             let mut data_guard = image_files.write().unwrap();
             for file in files {
+                let metadata = exif::read_metadata(&file.data);
+                // Nothing is kept between visits, so every open starts from the camera's ratings
+                let imported_rating =
+                    xmp::embedded_rating(&file.data, &metadata).unwrap_or_default();
+                let (rating, stars) = imported_rating.verdict();
                 data_guard.push(
                     ImageInfo {
+                        fingerprint: Some(content_fingerprint(&file.data)),
+                        metadata,
                         data: file.data,
                         image_name: file.name,
                         path_processed: PathBuf::new(),
                         path_raw: None,
-                        rating: Rating::Unrated,
+                        rating,
+                        stars,
+                        label: imported_rating.label,
                         texture: Arc::new(Mutex::new(None)),
+                        ..Default::default()
                     }
                     .into(),
                 );
             }
+            burst::assign_bursts(&mut data_guard);
         });
     }
 
@@ -167,7 +182,22 @@ impl BlitzApp {
         //    Create a Uint8Array view onto the ArrayBuffer
         let byte_array = Uint8Array::new(&array_buffer);
         //    Copy the data into a Rust Vec<u8>
-        let bytes: Arc<[u8]> = Arc::from(byte_array.to_vec());
+        let file_bytes = byte_array.to_vec();
+
+        // RAW files are shown through the JPEG preview the camera embedded in them
+        let bytes: Arc<[u8]> = if is_raw_file_name(&file_name) {
+            match raw_preview::extract_jpeg_preview(&file_bytes) {
+                Some(preview) => Arc::from(preview),
+                None => {
+                    return Err(JsValue::from_str(&format!(
+                        "No JPEG preview found in {}",
+                        file_name
+                    )))
+                }
+            }
+        } else {
+            Arc::from(file_bytes)
+        };
 
         log::debug!(
             "Read {} bytes from file: {}",
@@ -185,12 +215,19 @@ impl BlitzApp {
 fn is_valid_image_extension(filename: &str) -> bool {
     if let Some(ext) = get_file_extension(filename) {
         let lower_ext = ext.to_lowercase();
-        lower_ext == "jpg" || lower_ext == "png"
+        lower_ext == "jpg" || lower_ext == "png" || is_raw_file_name(filename)
     } else {
         false
     }
 }
 
+fn is_raw_file_name(filename: &str) -> bool {
+    match get_file_extension(filename) {
+        Some(ext) => is_raw_extension(OsStr::new(&ext)),
+        None => false,
+    }
+}
+
 fn get_file_extension(filename: &str) -> Option<String> {
     // Use the `rfind` method to find the last occurrence of '.' in the filename.
     let dot_index = filename.rfind('.');
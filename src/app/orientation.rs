@@ -0,0 +1,130 @@
+//! Turns the EXIF orientation plus the manual rotation into upright pixels, both for the
+//! textures we build ourselves and for images egui decodes from their URIs.
+
+use egui::ColorImage;
+use image::{metadata::Orientation, DynamicImage};
+
+/// Appended to `bytes://` URIs of images that have to be transformed while decoding.
+const ORIENTATION_URI_MARKER: &str = "#orientation=";
+
+/// Combines the EXIF orientation value with `quarter_turns` manual clockwise rotations.
+pub fn display_orientation(exif_orientation: Option<u16>, quarter_turns: u8) -> Orientation {
+    let exif_orientation = exif_orientation
+        .and_then(|value| u8::try_from(value).ok())
+        .and_then(Orientation::from_exif)
+        .unwrap_or(Orientation::NoTransforms);
+
+    // Every orientation is a horizontal flip (or not) followed by clockwise quarter turns
+    let (flip, turns) = match exif_orientation {
+        Orientation::NoTransforms => (false, 0),
+        Orientation::Rotate90 => (false, 1),
+        Orientation::Rotate180 => (false, 2),
+        Orientation::Rotate270 => (false, 3),
+        Orientation::FlipHorizontal => (true, 0),
+        Orientation::Rotate270FlipH => (true, 1),
+        Orientation::FlipVertical => (true, 2),
+        Orientation::Rotate90FlipH => (true, 3),
+    };
+
+    match (flip, (turns + quarter_turns) % 4) {
+        (false, 0) => Orientation::NoTransforms,
+        (false, 1) => Orientation::Rotate90,
+        (false, 2) => Orientation::Rotate180,
+        (false, _) => Orientation::Rotate270,
+        (true, 0) => Orientation::FlipHorizontal,
+        (true, 1) => Orientation::Rotate270FlipH,
+        (true, 2) => Orientation::FlipVertical,
+        (true, _) => Orientation::Rotate90FlipH,
+    }
+}
+
+/// Marks `uri` to be decoded in `orientation`, upright images keep the plain URI so the
+/// default loaders can handle them.
+pub fn oriented_uri(uri: String, orientation: Orientation) -> String {
+    match orientation {
+        Orientation::NoTransforms => uri,
+        _ => format!("{}{}{}", uri, ORIENTATION_URI_MARKER, orientation.to_exif()),
+    }
+}
+
+/// Splits `uri` into the URI before [`oriented_uri`] and the orientation it added.
+pub fn split_orientation(uri: &str) -> (&str, Option<Orientation>) {
+    let split = uri
+        .rsplit_once(ORIENTATION_URI_MARKER)
+        .and_then(|(rest, value)| {
+            let orientation = value.parse().ok().and_then(Orientation::from_exif)?;
+            Some((rest, orientation))
+        });
+    match split {
+        Some((rest, orientation)) => (rest, Some(orientation)),
+        None => (uri, None),
+    }
+}
+
+pub fn create_image(
+    image_data: &[u8],
+    orientation: Orientation,
+) -> Result<ColorImage, image::ImageError> {
+    Ok(to_color_image(
+        image::load_from_memory(image_data)?,
+        orientation,
+    ))
+}
+
+/// `image` turned to `orientation` and ready to become a texture.
+pub fn to_color_image(mut image: DynamicImage, orientation: Orientation) -> ColorImage {
+    image.apply_orientation(orientation);
+    let size = [image.width() as _, image.height() as _];
+    let image_buffer = image.to_rgba8();
+    let pixels = image_buffer.as_flat_samples();
+    ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_orientation() {
+        assert_eq!(Orientation::NoTransforms, display_orientation(None, 0));
+        assert_eq!(Orientation::Rotate90, display_orientation(Some(6), 0));
+        assert_eq!(Orientation::NoTransforms, display_orientation(Some(8), 1));
+        assert_eq!(Orientation::Rotate180, display_orientation(Some(6), 1));
+        assert_eq!(Orientation::Rotate90FlipH, display_orientation(Some(2), 3));
+        assert_eq!(Orientation::FlipVertical, display_orientation(Some(4), 0));
+        // Four turns always bring us back to the camera orientation
+        for exif in 1..=8 {
+            assert_eq!(
+                display_orientation(Some(exif), 0),
+                display_orientation(Some(exif), 4)
+            );
+        }
+    }
+
+    #[test]
+    fn test_create_image_applies_orientation() {
+        let data = std::fs::read("assets/samples/1.jpg").unwrap();
+        let upright = create_image(&data, Orientation::NoTransforms).unwrap();
+        let rotated = create_image(&data, Orientation::Rotate90).unwrap();
+        assert_eq!([upright.size[1], upright.size[0]], rotated.size);
+    }
+
+    #[test]
+    fn test_oriented_uri() {
+        let uri = || "bytes://IMG_0001.JPG".to_string();
+        assert_eq!(
+            "bytes://IMG_0001.JPG",
+            oriented_uri(uri(), Orientation::NoTransforms)
+        );
+        let rotated = oriented_uri(uri(), Orientation::Rotate270);
+        assert_eq!("bytes://IMG_0001.JPG#orientation=8", rotated);
+        assert_eq!(
+            ("bytes://IMG_0001.JPG", Some(Orientation::Rotate270)),
+            split_orientation(&rotated)
+        );
+        assert_eq!(
+            ("bytes://IMG_0001.JPG", None),
+            split_orientation("bytes://IMG_0001.JPG")
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use crate::BlitzApp;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+impl BlitzApp {
+    /// Shows how well the texture cache is doing and what egui's loaders hold, for tuning
+    /// `max_texture_count`. The bytes and pixels of a photo are only held until its texture is
+    /// uploaded, so the textures are what `max_texture_count` budgets.
+    pub fn update_cache_stats_overlay(&mut self, ctx: &egui::Context) {
+        if !self.show_cache_stats {
+            return;
+        }
+
+        let stats = self.texture_cache.stats;
+        let lookups = stats.hits + stats.misses;
+        let hit_rate = match lookups {
+            0 => 0.0,
+            _ => 100.0 * stats.hits as f64 / lookups as f64,
+        };
+        let loaders = ctx.loaders();
+        let bytes: usize = loaders
+            .bytes
+            .lock()
+            .iter()
+            .map(|loader| loader.byte_size())
+            .sum();
+        let images: usize = loaders
+            .image
+            .lock()
+            .iter()
+            .map(|loader| loader.byte_size())
+            .sum();
+        let textures: usize = loaders
+            .texture
+            .lock()
+            .iter()
+            .map(|loader| loader.byte_size())
+            .sum();
+
+        egui::Area::new(egui::Id::new("cache_stats_overlay"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!(
+                        "Textures: {} / {}  ({:.1} MiB)",
+                        self.texture_cache.len(),
+                        self.max_texture_count,
+                        textures as f64 / MIB
+                    ));
+                    ui.label(format!(
+                        "Hits: {}  Misses: {}  ({:.0}%)",
+                        stats.hits, stats.misses, hit_rate
+                    ));
+                    ui.label(format!("Evictions: {}", stats.evictions));
+                    ui.label(format!(
+                        "Loading: {:.1} MiB bytes  {:.1} MiB pixels",
+                        bytes as f64 / MIB,
+                        images as f64 / MIB
+                    ));
+                    ui.label(format!(
+                        "Total: {:.1} MiB",
+                        (bytes + images + textures) as f64 / MIB
+                    ));
+                });
+            });
+    }
+}
@@ -1,6 +1,7 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::sync::Arc;
 
-use crate::app::ImageInfo;
+use crate::app::{texture_cache, tiers::Tier, ImageInfo};
 use crate::BlitzApp;
 use egui::{Color32, Vec2};
 #[cfg(not(target_arch = "wasm32"))]
@@ -15,14 +16,22 @@ impl BlitzApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
             ui.heading("blitz");
-            ui.add(
-                egui::Slider::new(&mut self.max_texture_count, 0..=500).text("Max Texture Count"),
-            );
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::Slider::new(&mut self.max_texture_count, 0..=500)
+                        .text("Max Texture Count"),
+                );
+                ui.toggle_value(&mut self.show_cache_stats, "Cache Stats");
+            });
             self.handle_user_input(ctx, ui);
+            self.update_commit_preview(ui);
+            ui.toggle_value(&mut self.show_info_panel, "ℹ Info");
 
             if let Ok(photos) = self.photos.try_read() {
                 if !photos.is_empty() {
                     if let Some(current_image) = photos.get(photos_index) {
+                        self.update_info_panel(ui, current_image);
+
                         let max_height = ui.available_height();
                         let max_width = ui.available_width();
 
@@ -122,7 +131,7 @@ fn display_image(
                         println!("Image dragged");
                     }
                     if image_widget.hovered() {
-                        handle_hover_action(ctx, image_widget, texture);
+                        handle_hover_action(ctx, image_widget, texture.id());
 
                         // println!("{}", image_widget.rect);
                     }
@@ -139,22 +148,34 @@ fn display_image_with_bytes(
     max_width: f32,
     max_height: f32,
     ui: &mut egui::Ui,
-    _ctx: &egui::Context,
+    ctx: &egui::Context,
     current_image: &ImageInfo,
 ) -> egui::Response {
-    let bytes: Arc<[u8]> = current_image.data.clone();
-    let byte_path = format!("bytes://{}", current_image.image_name);
-    let image = egui::Image::from_bytes(byte_path, bytes)
+    texture_cache::mark_shown(ui.ctx(), current_image.image_uri(Tier::Screen));
+    let image = egui::Image::new(current_image.image_source(Tier::Screen))
         .max_width(max_width)
         .max_height(max_height);
 
-    ui.add(image)
+    let image_widget = ui.add(image);
+    // The loupe needs every pixel, so only zooming in loads the full resolution
+    if image_widget.hovered() {
+        texture_cache::mark_shown(ctx, current_image.image_uri(Tier::Full));
+        let full_resolution = current_image.image_source(Tier::Full).load(
+            ctx,
+            egui::TextureOptions::default(),
+            egui::SizeHint::default(),
+        );
+        if let Ok(egui::load::TexturePoll::Ready { texture }) = full_resolution {
+            handle_hover_action(ctx, image_widget.clone(), texture.id);
+        }
+    }
+    image_widget
 }
 
 fn handle_hover_action(
     ctx: &egui::Context,
     image_widget: egui::Response,
-    texture: &egui::TextureHandle,
+    texture_id: egui::TextureId,
 ) {
     // Draw the image at the cursor position
     if let Some(pos) = ctx.pointer_interact_pos() {
@@ -174,12 +195,7 @@ fn handle_hover_action(
         let normalized_pos = egui::pos2(normalized_x, normalized_y);
 
         let uv = egui::Rect::from_min_max(normalized_pos, normalized_pos + zoom_level);
-        painter.add(egui::Shape::image(
-            texture.id(),
-            pos_rect,
-            uv,
-            Color32::WHITE,
-        ));
+        painter.add(egui::Shape::image(texture_id, pos_rect, uv, Color32::WHITE));
     }
 }
 
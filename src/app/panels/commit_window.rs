@@ -0,0 +1,223 @@
+use crate::app::checksum::Manifest;
+use crate::app::commit_preview::{format_bytes, CommitPreview};
+use crate::BlitzApp;
+use egui::Color32;
+
+enum PreviewAction {
+    Confirm,
+    Cancel,
+}
+
+impl BlitzApp {
+    /// The modal listing what a commit will do, shown until it's confirmed or cancelled.
+    pub fn update_commit_preview(&mut self, ui: &mut egui::Ui) {
+        let Some(preview) = &self.commit_preview else {
+            return;
+        };
+
+        let modal = egui::Modal::new(egui::Id::new("commit_preview"))
+            .show(ui.ctx(), |ui| show_commit_preview(ui, preview));
+        let action = match modal.inner {
+            None if modal.should_close() => Some(PreviewAction::Cancel),
+            action => action,
+        };
+
+        match action {
+            Some(PreviewAction::Confirm) => {
+                if let Some(preview) = self.commit_preview.take() {
+                    self.commit_choices(ui, preview.journal);
+                }
+            }
+            Some(PreviewAction::Cancel) => self.commit_preview = None,
+            None => {}
+        }
+    }
+
+    /// Lists the files the last commit or undo couldn't move until the window is closed.
+    pub fn update_commit_failures_window(&mut self, ctx: &egui::Context) {
+        if self.commit_failures.is_empty() {
+            return;
+        }
+
+        let mut open = true;
+        egui::Window::new("Commit problems")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} file(s) couldn't be moved and stayed where they were:",
+                    self.commit_failures.len()
+                ));
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("commit_failures")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for failure in &self.commit_failures {
+                                    ui.label(failure.path.display().to_string());
+                                    ui.label(&failure.error);
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+
+        if !open {
+            self.commit_failures.clear();
+        }
+    }
+
+    /// Shows the result of "Verify Wheat Folder" until the window is closed.
+    pub fn update_verify_report_window(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.verify_report else {
+            return;
+        };
+
+        let mut open = true;
+        egui::Window::new("Wheat folder verification")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(report.dir.display().to_string());
+                if report.checked == 0 && report.problems.is_empty() {
+                    ui.label("There is no manifest yet, commit with verified checksums first.");
+                } else if report.problems.is_empty() {
+                    ui.colored_label(
+                        Color32::GREEN,
+                        format!("All {} file(s) match their checksum.", report.checked),
+                    );
+                } else {
+                    ui.colored_label(
+                        Color32::RED,
+                        format!(
+                            "{} of {} file(s) have problems:",
+                            report.problems.len(),
+                            report.checked
+                        ),
+                    );
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("verify_problems")
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for problem in &report.problems {
+                                        ui.label(problem.path.display().to_string());
+                                        ui.label(&problem.problem);
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                }
+            });
+
+        if !open {
+            self.verify_report = None;
+        }
+    }
+}
+
+fn show_commit_preview(ui: &mut egui::Ui, preview: &CommitPreview) -> Option<PreviewAction> {
+    ui.set_max_width(700.0);
+    ui.heading("Commit preview");
+    ui.label(format!("Mode: {}", preview.journal.mode.label()));
+    if preview.journal.verify_checksums {
+        ui.label(format!(
+            "Checksums are verified and recorded in {}",
+            Manifest::path(&preview.journal.wheat_dir).display()
+        ));
+    }
+
+    if preview.file_count() == 0 {
+        ui.label("Nothing is rated yet, there is nothing to commit.");
+    }
+
+    for destination in &preview.destinations {
+        ui.label(format!(
+            "{} photo(s), {} file(s) → {}",
+            destination.photo_count,
+            destination.file_count,
+            destination.dir.display()
+        ));
+        if !destination.has_room() {
+            ui.colored_label(
+                Color32::RED,
+                format!(
+                    "Needs {} on its volume but only {} is free there",
+                    format_bytes(destination.volume_bytes_needed),
+                    format_bytes(destination.bytes_free.unwrap_or(0))
+                ),
+            );
+        }
+    }
+
+    if !preview.journal.skipped.is_empty() {
+        ui.separator();
+        ui.colored_label(
+            Color32::YELLOW,
+            format!(
+                "{} photo(s) stay where they are because their name is taken:",
+                preview.journal.skipped.len()
+            ),
+        );
+        egui::ScrollArea::vertical()
+            .id_salt("commit_skipped")
+            .max_height(120.0)
+            .show(ui, |ui| {
+                for skipped in &preview.journal.skipped {
+                    ui.label(skipped.display().to_string());
+                }
+            });
+    }
+
+    if !preview.collisions.is_empty() {
+        ui.separator();
+        ui.colored_label(
+            Color32::YELLOW,
+            format!(
+                "{} file(s) will overwrite an existing file:",
+                preview.collisions.len()
+            ),
+        );
+        egui::ScrollArea::vertical()
+            .id_salt("commit_collisions")
+            .max_height(120.0)
+            .show(ui, |ui| {
+                for collision in &preview.collisions {
+                    ui.label(collision.display().to_string());
+                }
+            });
+    }
+
+    ui.separator();
+    egui::CollapsingHeader::new(format!("All files ({})", preview.file_count())).show(ui, |ui| {
+        egui::ScrollArea::vertical()
+            .id_salt("commit_moves")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for file_move in preview.journal.entries.iter().flat_map(|e| &e.moves) {
+                    ui.label(format!(
+                        "{} → {}",
+                        file_move.source.display(),
+                        file_move.destination.display()
+                    ));
+                }
+            });
+    });
+
+    ui.separator();
+    let mut action = None;
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(preview.can_commit(), egui::Button::new("Commit"))
+            .clicked()
+        {
+            action = Some(PreviewAction::Confirm);
+        }
+        if ui.button("Cancel").clicked() {
+            action = Some(PreviewAction::Cancel);
+        }
+    });
+    action
+}
@@ -0,0 +1,62 @@
+use crate::app::models::ImageInfo;
+use crate::BlitzApp;
+
+impl BlitzApp {
+    /// Shows the capture settings of `current_image` next to it, toggled with the Info button or I.
+    pub fn update_info_panel(&self, ui: &mut egui::Ui, current_image: &ImageInfo) {
+        egui::SidePanel::right("info_panel")
+            .resizable(false)
+            .show_animated_inside(ui, self.show_info_panel, |ui| {
+                ui.heading("Info");
+                let metadata = &current_image.metadata;
+
+                egui::Grid::new("info_grid")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        info_row(ui, "File", Some(current_image.image_name.clone()));
+                        info_row(ui, "Camera", metadata.camera());
+                        info_row(ui, "Lens", metadata.lens.clone());
+                        info_row(
+                            ui,
+                            "Focal length",
+                            metadata
+                                .focal_length
+                                .map(|focal_length| format!("{} mm", focal_length.round())),
+                        );
+                        info_row(
+                            ui,
+                            "Aperture",
+                            metadata
+                                .f_number
+                                .map(|f_number| format!("f/{:.1}", f_number)),
+                        );
+                        info_row(ui, "Shutter", metadata.exposure_time_label());
+                        info_row(ui, "ISO", metadata.iso.map(|iso| iso.to_string()));
+                        info_row(
+                            ui,
+                            "Captured",
+                            metadata.capture_time.map(|time| time.to_string()),
+                        );
+                        info_row(
+                            ui,
+                            "Orientation",
+                            metadata.orientation_label().map(str::to_string),
+                        );
+                        info_row(
+                            ui,
+                            "GPS",
+                            metadata.gps.map(|(latitude, longitude)| {
+                                format!("{:.5}, {:.5}", latitude, longitude)
+                            }),
+                        );
+                    });
+            });
+    }
+}
+
+fn info_row(ui: &mut egui::Ui, label: &str, value: Option<String>) {
+    ui.label(label);
+    ui.label(value.unwrap_or_else(|| "–".to_string()));
+    ui.end_row();
+}
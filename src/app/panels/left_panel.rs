@@ -1,25 +1,153 @@
+use crate::app::burst;
 use crate::app::context_menu;
 use crate::app::models::{ImageInfo, Rating};
+use crate::app::panels::{overlay, thumbnail};
+use crate::app::tiers::Tier;
 use crate::BlitzApp;
 use egui::ImageSource;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
 impl BlitzApp {
     pub fn update_left_panel(&mut self, ctx: &egui::Context) {
+        let previous_index = self.photos_index;
         egui::SidePanel::left("left_panel").show(ctx, |ui| {
             ui.label("Queue");
 
             egui::ScrollArea::vertical().show(ui, |ui| {
                 if let Ok(photos) = self.photos.try_read() {
-                    for (index, photo) in photos.iter().enumerate() {
-                        render_photo_item(photo, ui, index, &mut self.photos_index);
+                    let groups = group_by_subfolder(&self.photo_dir, &photos);
+                    let bursts = burst::burst_members(&photos);
+
+                    // A flat folder doesn't need any headers
+                    if groups.len() <= 1 {
+                        let indices: Vec<usize> = (0..photos.len()).collect();
+                        render_queue(
+                            &photos,
+                            &indices,
+                            &bursts,
+                            ui,
+                            &mut self.photos_index,
+                            &mut self.expanded_bursts,
+                        );
+                        return;
+                    }
+
+                    for (subfolder, indices) in groups {
+                        let unrated_count = indices
+                            .iter()
+                            .filter(|index| photos[**index].rating == Rating::Unrated)
+                            .count();
+                        egui::CollapsingHeader::new(format!("{} ({})", subfolder, unrated_count))
+                            .id_salt(&subfolder)
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                render_queue(
+                                    &photos,
+                                    &indices,
+                                    &bursts,
+                                    ui,
+                                    &mut self.photos_index,
+                                    &mut self.expanded_bursts,
+                                );
+                            });
                     }
                 }
             });
         });
+        self.record_jump_from(previous_index);
+    }
+}
+
+/// Renders the photos at `indices`, the frames of a burst are collapsed into one stack which
+/// sits where its first unrated frame would be.
+fn render_queue(
+    photos: &[ImageInfo],
+    indices: &[usize],
+    bursts: &HashMap<usize, Vec<usize>>,
+    ui: &mut egui::Ui,
+    photos_index: &mut usize,
+    expanded_bursts: &mut HashSet<usize>,
+) {
+    for &index in indices {
+        let photo = &photos[index];
+        let Some(frames) = photo.burst_id.and_then(|burst_id| bursts.get(&burst_id)) else {
+            render_photo_item(photo, ui, index, photos_index);
+            continue;
+        };
+
+        let unrated_frames: Vec<usize> = frames
+            .iter()
+            .copied()
+            .filter(|frame| photos[*frame].rating == Rating::Unrated)
+            .collect();
+        if unrated_frames.first() == Some(&index) {
+            render_burst_stack(photos, &unrated_frames, ui, photos_index, expanded_bursts);
+        }
     }
 }
 
+fn render_burst_stack(
+    photos: &[ImageInfo],
+    frames: &[usize],
+    ui: &mut egui::Ui,
+    photos_index: &mut usize,
+    expanded_bursts: &mut HashSet<usize>,
+) {
+    let first_frame = frames[0];
+    let Some(burst_id) = photos[first_frame].burst_id else {
+        return;
+    };
+    if frames.len() == 1 {
+        render_photo_item(&photos[first_frame], ui, first_frame, photos_index);
+        return;
+    }
+
+    let expanded = expanded_bursts.contains(&burst_id);
+    ui.horizontal(|ui| {
+        let toggle = if expanded { "⏷" } else { "⏵" };
+        if ui.small_button(toggle).clicked() {
+            if expanded {
+                expanded_bursts.remove(&burst_id);
+            } else {
+                expanded_bursts.insert(burst_id);
+            }
+        }
+        ui.label(format!("Burst of {}", frames.len()))
+            .on_hover_text("Press B to keep the frame on screen and reject the rest");
+    });
+
+    if expanded {
+        ui.indent(("burst", burst_id), |ui| {
+            for &frame in frames {
+                render_photo_item(&photos[frame], ui, frame, photos_index);
+            }
+        });
+    } else {
+        render_photo_item(&photos[first_frame], ui, first_frame, photos_index);
+    }
+}
+
+/// Groups the photo indices by the subfolder of `photo_dir` they live in, keeping the scan order.
+fn group_by_subfolder(photo_dir: &Path, photos: &[ImageInfo]) -> Vec<(String, Vec<usize>)> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for (index, photo) in photos.iter().enumerate() {
+        let subfolder = photo
+            .path_processed
+            .parent()
+            .and_then(|parent| parent.strip_prefix(photo_dir).ok())
+            .map(|relative| relative.display().to_string())
+            .filter(|relative| !relative.is_empty())
+            .unwrap_or_else(|| ".".to_string());
+
+        match groups.iter_mut().find(|(name, _)| *name == subfolder) {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((subfolder, vec![index])),
+        }
+    }
+    groups
+}
+
 fn render_photo_item(photo: &ImageInfo, ui: &mut egui::Ui, index: usize, photos_index: &mut usize) {
     match photo.rating {
         Rating::Unrated => render_unrated_photo(photo, ui, index, photos_index),
@@ -46,26 +174,12 @@ fn display_thumbnail(
     if let Ok(texture_handle_guard) = photo.texture.try_lock() {
         let image_source: ImageSource<'_> = match *texture_handle_guard {
             Some(ref texture) => texture.into(),
-            None => {
-                // "file://assets/icon-1024.png".into()
-                let bytes: Arc<[u8]> = photo.data.clone();
-                let byte_path = format!("bytes://{}", photo.image_name);
-                ImageSource::Bytes {
-                    uri: byte_path.into(),
-                    bytes: egui::load::Bytes::Shared(bytes),
-                }
-            }
+            None => photo.image_source(Tier::Thumbnail),
         };
 
-        let image = egui::Image::new(image_source)
-            .max_width(100.0)
-            .sense(egui::Sense {
-                click: true,
-                drag: false,
-                focusable: false,
-            });
-
-        let image_widget = ui.add(image);
+        let image = egui::Image::new(image_source).max_width(100.0);
+        let image_widget = thumbnail::add_thumbnail(ui, photo, image);
+        overlay::paint_rating_overlay(ui, image_widget.rect, photo);
         if image_widget.clicked() {
             *app_photo_index = index
         }
@@ -1,3 +1,5 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::app::{collision_policy::CollisionPolicy, commit_mode::CommitMode};
 use crate::BlitzApp;
 
 impl BlitzApp {
@@ -19,6 +21,69 @@ impl BlitzApp {
                     ui.close_menu();
                 }
 
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.menu_button("Commit Mode", |ui| {
+                    for mode in CommitMode::ALL {
+                        if ui
+                            .radio_value(&mut self.commit_mode, mode, mode.label())
+                            .clicked()
+                        {
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.checkbox(&mut self.verify_checksums, "Verify Checksums");
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.checkbox(&mut self.write_xmp_sidecars, "Write XMP Sidecars");
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Verify Wheat Folder").clicked() {
+                    self.verify_wheat_dir();
+                    ui.close_menu();
+                }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.menu_button("Name Collisions", |ui| {
+                    for policy in CollisionPolicy::ALL {
+                        if ui
+                            .radio_value(&mut self.collision_policy, policy, policy.label())
+                            .clicked()
+                        {
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.menu_button("Thumbnail Cache", |ui| {
+                    ui.label(format!(
+                        "{:.1} MiB in .blitz/cache",
+                        self.disk_cache.size() as f64 / (1024.0 * 1024.0)
+                    ));
+                    let limit = ui.add(
+                        egui::Slider::new(&mut self.disk_cache_limit_mb, 50..=10_000)
+                            .logarithmic(true)
+                            .text("MiB at most"),
+                    );
+                    if limit.changed() {
+                        self.disk_cache
+                            .set_limit(self.disk_cache_limit_mb * 1024 * 1024);
+                    }
+                    if ui.button("Clear Cache").clicked() {
+                        self.clear_disk_cache();
+                        ui.close_menu();
+                    }
+                });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Settings…").clicked() {
+                    self.template_draft = Some(self.destination_template.clone());
+                    ui.close_menu();
+                }
+
                 ui.add_space(10.0);
 
                 #[cfg(not(target_arch = "wasm32"))]
@@ -27,6 +92,9 @@ impl BlitzApp {
                     log::debug!("Chose {:?} as chaffe directory", self.chaffe_dir_target);
                     ui.close_menu();
                 }
+
+                #[cfg(all(unix, not(target_os = "macos")))]
+                ui.checkbox(&mut self.trash_rejects, "Send Rejects to Trash");
             });
             ui.add_space(16.0);
         }
@@ -1,5 +1,12 @@
+mod cache_stats_overlay;
 mod center_panel;
+mod commit_window;
+mod info_panel;
 mod left_panel;
 mod menu_bar;
+mod overlay;
 mod right_panel;
+mod settings_window;
+mod storage_window;
+mod thumbnail;
 mod top_panel;
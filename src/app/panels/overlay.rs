@@ -0,0 +1,30 @@
+use crate::app::models::ImageInfo;
+
+/// Paints the stars and the color label of `photo` on top of its thumbnail.
+pub fn paint_rating_overlay(ui: &egui::Ui, thumbnail_rect: egui::Rect, photo: &ImageInfo) {
+    let painter = ui.painter_at(thumbnail_rect);
+
+    if let Some(label) = photo.label {
+        let center = thumbnail_rect.right_top() + egui::vec2(-8.0, 8.0);
+        painter.circle(
+            center,
+            5.0,
+            label.color(),
+            egui::Stroke::new(1.0_f32, egui::Color32::BLACK),
+        );
+    }
+
+    if photo.stars > 0 {
+        let text = "★".repeat(photo.stars as usize);
+        let position = thumbnail_rect.left_bottom() + egui::vec2(4.0, -4.0);
+        let font = egui::FontId::proportional(12.0);
+        let galley = painter.layout_no_wrap(text, font, egui::Color32::from_rgb(255, 215, 0));
+        let text_rect = egui::Align2::LEFT_BOTTOM.anchor_size(position, galley.size());
+        painter.rect_filled(
+            text_rect.expand(2.0),
+            2.0,
+            egui::Color32::from_black_alpha(160),
+        );
+        painter.galley(text_rect.min, galley, egui::Color32::WHITE);
+    }
+}
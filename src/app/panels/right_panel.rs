@@ -2,47 +2,61 @@
 use crate::app::context_menu;
 use crate::app::models::ImageInfo;
 use crate::app::models::Rating;
+use crate::app::panels::{overlay, thumbnail};
+use crate::app::tiers::Tier;
 use crate::BlitzApp;
-use std::sync::Arc;
 
 impl BlitzApp {
     pub fn update_right_panel(&mut self, ctx: &egui::Context) {
+        let previous_index = self.photos_index;
         egui::SidePanel::right("right_panel").show(ctx, |ui| {
             ui.label("Keep");
 
             if let Ok(photos) = self.photos.try_read() {
-                for photo in photos.iter().rev() {
-                    render_photo_image(photo, ui);
+                for (index, photo) in photos.iter().enumerate().rev() {
+                    render_photo_image(photo, ui, index, &mut self.photos_index);
                 }
             }
         });
+        self.record_jump_from(previous_index);
     }
 }
 
 #[allow(unused_variables)]
-fn render_photo_image(current_image: &ImageInfo, ui: &mut egui::Ui) {
+fn render_photo_image(
+    current_image: &ImageInfo,
+    ui: &mut egui::Ui,
+    index: usize,
+    photos_index: &mut usize,
+) {
     match current_image.rating {
         Rating::Unrated => {}
         Rating::Approve => {
-            handle_approve_image(current_image, ui);
+            handle_approve_image(current_image, ui, index, photos_index);
         }
         Rating::Remove => {}
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn handle_approve_image(photo: &ImageInfo, ui: &mut egui::Ui) {
+fn handle_approve_image(
+    photo: &ImageInfo,
+    ui: &mut egui::Ui,
+    index: usize,
+    photos_index: &mut usize,
+) {
     if let Ok(texture_handle) = photo.texture.try_lock() {
         let texture = texture_handle.as_ref();
         let image = match texture {
             Some(texture) => egui::Image::new(texture).max_width(100.0),
-            None => {
-                let bytes: Arc<[u8]> = photo.data.clone();
-                let byte_path = format!("bytes://{}", photo.image_name);
-                egui::Image::from_bytes(byte_path, bytes).max_height(100.0)
-            }
+            None => egui::Image::new(photo.image_source(Tier::Thumbnail)).max_height(100.0),
         };
-        let image_widget = ui.add(image);
+        // Clicking a keeper brings it to the center to score it with stars and labels
+        let image_widget = thumbnail::add_thumbnail(ui, photo, image);
+        overlay::paint_rating_overlay(ui, image_widget.rect, photo);
+        if image_widget.clicked() {
+            *photos_index = index;
+        }
         image_widget.context_menu(|ui| {
             context_menu::add_open_file_location_option(photo, ui);
             context_menu::add_open_file_option(photo, ui);
@@ -53,18 +67,23 @@ fn handle_approve_image(photo: &ImageInfo, ui: &mut egui::Ui) {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn handle_approve_image(photo: &ImageInfo, ui: &mut egui::Ui) {
+fn handle_approve_image(
+    photo: &ImageInfo,
+    ui: &mut egui::Ui,
+    index: usize,
+    photos_index: &mut usize,
+) {
     if let Ok(texture_handle) = photo.texture.try_lock() {
         let texture = texture_handle.as_ref();
         let image = match texture {
             Some(texture) => egui::Image::new(texture).max_width(100.0),
-            None => {
-                let bytes: Arc<[u8]> = photo.data.clone();
-                let byte_path = format!("bytes://{}", photo.image_name);
-                egui::Image::from_bytes(byte_path, bytes).max_height(100.0)
-            }
+            None => egui::Image::new(photo.image_source(Tier::Thumbnail)).max_height(100.0),
         };
-        let _image_widget = ui.add(image);
+        let image_widget = thumbnail::add_thumbnail(ui, photo, image);
+        overlay::paint_rating_overlay(ui, image_widget.rect, photo);
+        if image_widget.clicked() {
+            *photos_index = index;
+        }
 
         ui.label(photo.image_name.clone());
     }
@@ -0,0 +1,71 @@
+use crate::app::destination_template::{DestinationTemplate, DEFAULT_TEMPLATE, FIELDS};
+use crate::BlitzApp;
+use egui::Color32;
+
+impl BlitzApp {
+    /// Edits the destination template, shown while there's a draft.
+    pub fn update_settings_window(&mut self, ctx: &egui::Context) {
+        let Some(mut draft) = self.template_draft.take() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Destination inside the wheat and chaffe folders:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut draft).desired_width(400.0));
+                    if ui.button("Reset").clicked() {
+                        draft = DEFAULT_TEMPLATE.to_string();
+                    }
+                });
+
+                let parsed = DestinationTemplate::parse(&draft);
+                match &parsed {
+                    Ok(template) => {
+                        let photos = self.photos.read().unwrap();
+                        if let Some(photo) = photos.get(self.photos_index) {
+                            let example = template.render(photo, &photo.path_processed);
+                            ui.label(format!("Current picture: {}", example.display()));
+                        }
+                    }
+                    Err(err) => {
+                        ui.colored_label(Color32::RED, format!("Invalid template: {}", err));
+                    }
+                }
+
+                ui.collapsing("Fields", |ui| {
+                    egui::Grid::new("template_fields")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (name, description) in FIELDS {
+                                ui.monospace(format!("{{{}}}", name));
+                                ui.label(description);
+                                ui.end_row();
+                            }
+                        });
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let changed = draft != self.destination_template;
+                    if ui
+                        .add_enabled(parsed.is_ok() && changed, egui::Button::new("Apply"))
+                        .clicked()
+                    {
+                        apply = true;
+                    }
+                });
+            });
+
+        if apply {
+            self.destination_template = draft.clone();
+        }
+        if open {
+            self.template_draft = Some(draft);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::BlitzApp;
+
+impl BlitzApp {
+    /// Explains why the folder starts over instead of restoring its ratings.
+    pub fn update_storage_warning_window(&mut self, ctx: &egui::Context) {
+        let Some(storage_warning) = &self.storage_warning else {
+            return;
+        };
+
+        let mut open = true;
+        egui::Window::new("Culling progress not restored")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.set_max_width(500.0);
+                ui.label(storage_warning);
+            });
+
+        if !open {
+            self.storage_warning = None;
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::app::{models::ImageInfo, texture_cache, tiers::Tier};
+
+/// What a thumbnail that was never on screen takes up, a landscape photo at 100 px wide.
+const PLACEHOLDER_SIZE: egui::Vec2 = egui::vec2(100.0, 67.0);
+
+/// Adds `image` of `photo` if it's on screen and just reserves its space otherwise, so a long
+/// queue doesn't load every photo in it. Either way the thumbnail can be clicked. `image` is
+/// expected to show the thumbnail tier.
+pub fn add_thumbnail(
+    ui: &mut egui::Ui,
+    photo: &ImageInfo,
+    image: egui::Image<'_>,
+) -> egui::Response {
+    let uri = photo.image_uri(Tier::Thumbnail);
+    // Off screen thumbnails keep the size they had, so the list doesn't jump while scrolling
+    let size_id = egui::Id::new(("thumbnail_size", &uri));
+    let size = ui
+        .data(|data| data.get_temp(size_id))
+        .unwrap_or(PLACEHOLDER_SIZE);
+    let placeholder = egui::Rect::from_min_size(ui.cursor().min, size);
+    if !ui.is_rect_visible(placeholder) {
+        return ui.allocate_response(size, egui::Sense::click());
+    }
+
+    let response = ui.add(image.sense(egui::Sense::click()));
+    ui.data_mut(|data| data.insert_temp(size_id, response.rect.size()));
+    texture_cache::mark_shown(ui.ctx(), uri);
+    response
+}
@@ -0,0 +1,96 @@
+//! URL escaping for paths, for the trash info files and the `photo://` URIs. The bytes are
+//! escaped as they are, names that aren't UTF-8 have to come back the same.
+
+use std::path::{Path, PathBuf};
+
+/// Only unreserved characters and `/` are kept.
+pub fn encode_path(path: &Path) -> String {
+    path_bytes(path)
+        .into_iter()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The path `encode_path` escaped, `None` for broken escapes.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))] // only native photos have paths to load
+pub fn decode_path(encoded: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                &tail[2..]
+            }
+            byte => {
+                bytes.push(byte);
+                tail
+            }
+        };
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(
+            "/home/me/Shoot%201/IMG_0001%23.JPG",
+            encode_path(Path::new("/home/me/Shoot 1/IMG_0001#.JPG"))
+        );
+        assert_eq!(
+            "/photos/%C3%A9t%C3%A9",
+            encode_path(Path::new("/photos/été"))
+        );
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            // Latin-1 from an old camera card
+            let latin1 = Path::new(OsStr::from_bytes(b"/photos/\xe9t\xe9"));
+            assert_eq!("/photos/%E9t%E9", encode_path(latin1));
+            assert_eq!(Some(latin1.to_path_buf()), decode_path("/photos/%E9t%E9"));
+        }
+    }
+
+    #[test]
+    fn test_decode_path() {
+        let path = Path::new("/home/me/Shoot 1/IMG_0001#tier=thumbnail.JPG");
+        assert_eq!(Some(path.to_path_buf()), decode_path(&encode_path(path)));
+        assert_eq!(None, decode_path("/photos/%E"));
+        assert_eq!(None, decode_path("/photos/%ZZ"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Reads photos from disk when they are about to be shown, so opening a folder only has to
+//! look at the start of every file. egui asks for the bytes through `photo://` URIs, see
+//! [`PhotoLoader`], and `texture_cache` decides when they are forgotten again.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use egui::load::{BytesLoadResult, BytesLoader, BytesPoll, LoadError};
+
+use super::{
+    fingerprint::{sampled_fingerprint, SAMPLE_LEN},
+    orientation, percent_encoding,
+    raw_pairing::is_raw_extension,
+    raw_preview, tiers,
+    worker_pool::WorkerPool,
+};
+
+pub const URI_SCHEME: &str = "photo://";
+
+/// How much of a file the folder scan reads. JPEGs keep their EXIF block in the first 64 KiB,
+/// RAW files put theirs before the sensor data.
+const HEAD_LEN: usize = 256 * 1024;
+
+/// The URI egui loads the photo at `path` from. The path is escaped, so any name comes back
+/// the same and a `#` in it isn't taken for the tier or orientation.
+pub fn photo_uri(path: &Path) -> String {
+    format!("{}{}", URI_SCHEME, percent_encoding::encode_path(path))
+}
+
+/// The path `photo_uri` made `source` from, `None` for other URIs.
+pub fn photo_path(source: &str) -> Option<PathBuf> {
+    source
+        .strip_prefix(URI_SCHEME)
+        .and_then(percent_encoding::decode_path)
+}
+
+/// The bytes egui decodes for the photo at `path`, the embedded JPEG preview for RAW files.
+pub fn read_photo(path: &Path) -> io::Result<Vec<u8>> {
+    let is_raw = path.extension().is_some_and(is_raw_extension);
+    if !is_raw {
+        return fs::read(path);
+    }
+
+    // The preview is a small part of a RAW file, so only the headers and the preview are read
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(HEAD_LEN as u64).read_to_end(&mut head)?;
+    let ranges = raw_preview::preview_ranges(&head);
+    if ranges.is_empty() && len > head.len() as u64 {
+        // The headers may point past the head, the whole file has them
+        let data = fs::read(path)?;
+        return raw_preview::extract_jpeg_preview(&data)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(no_preview);
+    }
+    for range in ranges.into_iter().filter(|range| range.end as u64 <= len) {
+        let mut jpeg = vec![0; range.len()];
+        file.seek(SeekFrom::Start(range.start as u64))?;
+        file.read_exact(&mut jpeg)?;
+        if raw_preview::is_decodable_jpeg(&jpeg) {
+            return Ok(jpeg);
+        }
+    }
+    Err(no_preview())
+}
+
+fn no_preview() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "no JPEG preview found")
+}
+
+/// The start of a file along with its fingerprint, everything the folder scan needs.
+pub struct FileHead {
+    pub head: Vec<u8>,
+    pub fingerprint: String,
+}
+
+/// Reads the first [`HEAD_LEN`] bytes of `path` and the end the fingerprint needs.
+pub fn read_head(path: &Path) -> io::Result<FileHead> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(HEAD_LEN as u64).read_to_end(&mut head)?;
+
+    let tail_start = len.saturating_sub(SAMPLE_LEN as u64);
+    // Small files were read whole already
+    let fingerprint = match len <= head.len() as u64 {
+        true => sampled_fingerprint(len, &head, &head[tail_start as usize..]),
+        false => {
+            let mut tail = Vec::new();
+            file.seek(SeekFrom::Start(tail_start))?;
+            file.read_to_end(&mut tail)?;
+            sampled_fingerprint(len, &head, &tail)
+        }
+    };
+    Ok(FileHead { head, fingerprint })
+}
+
+#[derive(Clone)]
+enum Entry {
+    Loading,
+    Loaded(Result<Arc<[u8]>, String>),
+}
+
+/// The URI the bytes of `uri` are kept under, without the orientation and tier.
+fn source_uri(uri: &str) -> &str {
+    tiers::split_tier(orientation::split_orientation(uri).0).0
+}
+
+/// Reads `photo://` URIs on the workers of a [`WorkerPool`]. All tiers and rotations of a
+/// photo share the same bytes, only the decoded image differs.
+pub struct PhotoLoader {
+    cache: Arc<Mutex<HashMap<String, Entry>>>,
+    workers: WorkerPool,
+}
+
+impl PhotoLoader {
+    pub fn new(workers: WorkerPool) -> Self {
+        Self {
+            cache: Default::default(),
+            workers,
+        }
+    }
+}
+
+impl BytesLoader for PhotoLoader {
+    fn id(&self) -> &str {
+        egui::generate_loader_id!(PhotoLoader)
+    }
+
+    fn load(&self, ctx: &egui::Context, uri: &str) -> BytesLoadResult {
+        let uri = source_uri(uri);
+        let Some(path) = photo_path(uri) else {
+            return Err(LoadError::NotSupported);
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(uri) {
+            Some(Entry::Loaded(Ok(bytes))) => {
+                return Ok(BytesPoll::Ready {
+                    size: None,
+                    bytes: egui::load::Bytes::Shared(bytes.clone()),
+                    mime: None,
+                })
+            }
+            Some(Entry::Loaded(Err(err))) => return Err(LoadError::Loading(err.clone())),
+            Some(Entry::Loading) => return Ok(BytesPoll::Pending { size: None }),
+            None => {}
+        }
+
+        let uri = uri.to_string();
+        let (worker_cache, worker_ctx) = (self.cache.clone(), ctx.clone());
+        cache.insert(uri.clone(), Entry::Loading);
+        self.workers.execute(move || {
+            // Forgotten while waiting for a worker, e.g. because another folder was opened
+            if !worker_cache.lock().unwrap().contains_key(&uri) {
+                return;
+            }
+            let result = read_photo(&path)
+                .map(Arc::from)
+                .map_err(|err| format!("{}: {}", path.display(), err));
+            if let Some(entry) = worker_cache.lock().unwrap().get_mut(&uri) {
+                *entry = Entry::Loaded(result);
+            }
+            worker_ctx.request_repaint();
+        });
+        Ok(BytesPoll::Pending { size: None })
+    }
+
+    fn forget(&self, uri: &str) {
+        let uri = source_uri(uri);
+        self.cache.lock().unwrap().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.cache
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| match entry {
+                Entry::Loaded(Ok(bytes)) => bytes.len(),
+                Entry::Loaded(Err(err)) => err.len(),
+                Entry::Loading => 0,
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::fingerprint::content_fingerprint;
+    use crate::app::test_dir::TestDir;
+
+    #[test]
+    fn test_read_head_fingerprints_like_the_whole_file() {
+        let dir = TestDir::new("read_head");
+        let large: Vec<u8> = (0..HEAD_LEN * 3).map(|index| (index % 251) as u8).collect();
+        for (name, data) in [("small.jpg", &large[..1000]), ("large.jpg", &large[..])] {
+            fs::write(dir.join(name), data).unwrap();
+            let file_head = read_head(&dir.join(name)).unwrap();
+            assert_eq!(content_fingerprint(data), file_head.fingerprint);
+            assert_eq!(&data[..data.len().min(HEAD_LEN)], &file_head.head[..]);
+        }
+    }
+
+    #[test]
+    fn test_read_photo_reads_the_preview_past_the_head() {
+        let dir = TestDir::new("read_raw_preview");
+        let preview = fs::read("assets/samples/2.jpg").unwrap();
+        // The sensor data comes before the preview in this one
+        let offset = HEAD_LEN * 2;
+        let mut raf = b"FUJIFILMCCD-RAW ".to_vec();
+        raf.resize(offset, 0);
+        raf[84..88].copy_from_slice(&(offset as u32).to_be_bytes());
+        raf[88..92].copy_from_slice(&(preview.len() as u32).to_be_bytes());
+        raf.extend_from_slice(&preview);
+        fs::write(dir.join("DSCF0001.RAF"), &raf).unwrap();
+
+        assert_eq!(preview, read_photo(&dir.join("DSCF0001.RAF")).unwrap());
+    }
+}
@@ -0,0 +1,319 @@
+//! Decodes the photos the user is about to move to on background threads, so pressing D or
+//! rating with the arrow keys shows the next photo right away. The screen-fit images reach egui
+//! through [`PrefetchedImageLoader`] and are uploaded as textures as soon as they are done.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use egui::{
+    load::{ImageLoadResult, ImageLoader, ImagePoll, LoadError, SizeHint},
+    ColorImage, TextureOptions,
+};
+use image::metadata::Orientation;
+
+use super::{
+    disk_cache::DiskCache,
+    models::ImageInfo,
+    navigation::{get_next_picture_index, get_previous_picture_index, Direction},
+    orientation::to_color_image,
+    photo_loader::read_photo,
+    tiers::{self, Tier},
+    worker_pool::WorkerPool,
+    BlitzApp,
+};
+
+/// How many unrated photos ahead of the current one are decoded.
+const PREFETCH_COUNT: usize = 3;
+
+/// Decoding a photo is slow enough that a few of them in parallel pays off, but the UI thread
+/// needs a core too.
+const MAX_WORKERS: usize = 3;
+
+/// The photos to have ready, the current one first and then the next unrated ones in
+/// `direction`, the way navigation would reach them.
+pub fn prefetch_targets(
+    photos: &[ImageInfo],
+    current: usize,
+    direction: Direction,
+    count: usize,
+) -> Vec<usize> {
+    if current >= photos.len() {
+        return Vec::new();
+    }
+    let mut targets = vec![current];
+    while targets.len() <= count {
+        let last = targets[targets.len() - 1];
+        let next = match direction {
+            Direction::Forward => get_next_picture_index(last, photos),
+            Direction::Backward => get_previous_picture_index(last, photos),
+        };
+        // Fewer unrated photos than we'd like to prefetch, we went around once
+        match next {
+            Some(index) if !targets.contains(&index) => targets.push(index),
+            _ => break,
+        }
+    }
+    targets
+}
+
+#[derive(Clone)]
+struct Job {
+    uri: String,
+    path: PathBuf,
+    orientation: Orientation,
+}
+
+#[derive(Default)]
+struct Shared {
+    /// The URIs that are still worth decoding, jobs for any other URI are stale.
+    wanted: HashSet<String>,
+    /// Waiting for a worker or being decoded.
+    queued: HashSet<String>,
+    /// Decoded photos waiting to be uploaded.
+    decoded: HashMap<String, Arc<ColorImage>>,
+    /// Left to egui's loaders, which show the error.
+    failed: HashSet<String>,
+}
+
+/// Hands out the photos the workers decoded and keeps egui from decoding a photo on the UI
+/// thread while a worker is already at it. Registered last, so egui asks it first.
+struct PrefetchedImageLoader {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ImageLoader for PrefetchedImageLoader {
+    fn id(&self) -> &str {
+        egui::generate_loader_id!(PrefetchedImageLoader)
+    }
+
+    fn load(&self, _: &egui::Context, uri: &str, _: SizeHint) -> ImageLoadResult {
+        let shared = self.shared.lock().unwrap();
+        match shared.decoded.get(uri) {
+            Some(image) => Ok(ImagePoll::Ready {
+                image: image.clone(),
+            }),
+            None if shared.queued.contains(uri) => Ok(ImagePoll::Pending { size: None }),
+            None => Err(LoadError::NotSupported),
+        }
+    }
+
+    fn forget(&self, uri: &str) {
+        self.shared.lock().unwrap().decoded.remove(uri);
+    }
+
+    fn forget_all(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.decoded.clear();
+        shared.failed.clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.shared
+            .lock()
+            .unwrap()
+            .decoded
+            .values()
+            .map(|image| image.pixels.len() * std::mem::size_of::<egui::Color32>())
+            .sum()
+    }
+}
+
+/// A pool of decode workers fed with the photos the user is likely to look at next.
+pub struct Prefetcher {
+    workers: WorkerPool,
+    shared: Arc<Mutex<Shared>>,
+    disk_cache: Arc<DiskCache>,
+    ctx: egui::Context,
+}
+
+impl Prefetcher {
+    /// Starts the workers and registers the loader that hands their photos to `ctx`. Decoded
+    /// photos are kept in `disk_cache` as well.
+    pub fn spawn(ctx: &egui::Context, disk_cache: Arc<DiskCache>) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let workers = thread::available_parallelism()
+            .map_or(1, |cores| cores.get().saturating_sub(1))
+            .clamp(1, MAX_WORKERS);
+
+        ctx.add_image_loader(Arc::new(PrefetchedImageLoader {
+            shared: shared.clone(),
+        }));
+        Self {
+            workers: WorkerPool::spawn("prefetch", workers),
+            shared,
+            disk_cache,
+            ctx: ctx.clone(),
+        }
+    }
+
+    /// Makes `targets` the photos to have ready, in that order, and queues the ones that aren't
+    /// `loaded` yet. Queued photos that aren't among them anymore are skipped, which is what
+    /// happens to most of them when the user jumps.
+    fn retarget(&self, targets: &[Job], loaded: impl Fn(&str) -> bool) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.wanted = targets.iter().map(|job| job.uri.clone()).collect();
+        for job in targets {
+            let is_known = shared.queued.contains(&job.uri)
+                || shared.decoded.contains_key(&job.uri)
+                || shared.failed.contains(&job.uri);
+            if is_known || loaded(&job.uri) {
+                continue;
+            }
+            shared.queued.insert(job.uri.clone());
+            let (job, shared) = (job.clone(), self.shared.clone());
+            let (disk_cache, ctx) = (self.disk_cache.clone(), self.ctx.clone());
+            self.workers
+                .execute(move || run(job, &shared, &disk_cache, &ctx));
+        }
+    }
+
+    /// The photos the workers decoded so far.
+    fn decoded(&self) -> Vec<String> {
+        self.shared
+            .lock()
+            .unwrap()
+            .decoded
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn remove_decoded(&self, uri: &str) {
+        self.shared.lock().unwrap().decoded.remove(uri);
+    }
+}
+
+fn run(job: Job, shared: &Mutex<Shared>, disk_cache: &DiskCache, ctx: &egui::Context) {
+    {
+        let mut shared = shared.lock().unwrap();
+        if !shared.wanted.contains(&job.uri) {
+            shared.queued.remove(&job.uri);
+            return;
+        }
+    }
+
+    let decoded = decode(&job, disk_cache, tiers::screen_size(ctx));
+    let mut shared = shared.lock().unwrap();
+    shared.queued.remove(&job.uri);
+    match decoded {
+        Ok(image) if shared.wanted.contains(&job.uri) => {
+            shared.decoded.insert(job.uri, Arc::new(image));
+        }
+        Ok(_) => {}
+        Err(err) => {
+            log::warn!("Couldn't prefetch {}: {}", job.path.display(), err);
+            shared.failed.insert(job.uri);
+        }
+    }
+    ctx.request_repaint();
+}
+
+/// The screen-fit image of the photo of `job`, from the disk cache if a previous session
+/// decoded it already.
+fn decode(job: &Job, disk_cache: &DiskCache, screen_size: u32) -> Result<ColorImage, String> {
+    if let Some(image) = disk_cache.load(&job.path, Tier::Screen, screen_size) {
+        return Ok(to_color_image(image, job.orientation));
+    }
+    let bytes = read_photo(&job.path).map_err(|err| err.to_string())?;
+    let image =
+        tiers::scale_down(&bytes, Tier::Screen, screen_size).map_err(|err| err.to_string())?;
+    disk_cache.store(&job.path, Tier::Screen, screen_size, &image);
+    Ok(to_color_image(image, job.orientation))
+}
+
+impl BlitzApp {
+    /// Keeps the workers busy with the photos ahead in the direction the user is going and
+    /// uploads the ones they finished.
+    pub fn prefetch(&mut self, ctx: &egui::Context) {
+        let Ok(photos) = self.photos.try_read() else {
+            return;
+        };
+        let targets: Vec<Job> = self
+            .prefetched_photos(&photos)
+            .into_iter()
+            .map(|photo| Job {
+                uri: photo.image_uri(Tier::Screen),
+                path: photo.path_processed.clone(),
+                orientation: photo.display_orientation(),
+            })
+            .collect();
+        let prefetcher = self
+            .prefetcher
+            .get_or_insert_with(|| Prefetcher::spawn(ctx, self.disk_cache.clone()));
+        prefetcher.retarget(&targets, |uri| self.texture_cache.contains(uri));
+
+        let pass = ctx.cumulative_pass_nr();
+        for uri in prefetcher.decoded() {
+            if targets.iter().any(|job| job.uri == uri) {
+                let _ = ctx.try_load_texture(&uri, TextureOptions::default(), SizeHint::default());
+                self.texture_cache.prefetched(uri.clone(), pass);
+            }
+            // Uploaded or not needed anymore, either way the pixels can go
+            prefetcher.remove_decoded(&uri);
+        }
+    }
+
+    /// The photos [`Self::prefetch`] keeps ready, their screen-fit textures are pinned too.
+    pub fn prefetched_photos<'a>(&self, photos: &'a [ImageInfo]) -> Vec<&'a ImageInfo> {
+        prefetch_targets(
+            photos,
+            self.photos_index,
+            self.navigation_direction,
+            PREFETCH_COUNT,
+        )
+        .into_iter()
+        .map(|index| &photos[index])
+        // Photos kept in memory were never on disk to begin with
+        .filter(|photo| photo.data.is_empty())
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::Rating;
+
+    #[test]
+    fn test_prefetch_targets_follow_navigation() {
+        let ratings = [
+            Rating::Unrated,
+            Rating::Approve,
+            Rating::Unrated,
+            Rating::Unrated,
+            Rating::Remove,
+            Rating::Unrated,
+        ];
+        let photos: Vec<ImageInfo> = ratings
+            .into_iter()
+            .map(|rating| ImageInfo {
+                rating,
+                ..Default::default()
+            })
+            .collect();
+
+        assert_eq!(
+            vec![0, 2, 3, 5],
+            prefetch_targets(&photos, 0, Direction::Forward, 3)
+        );
+        assert_eq!(
+            vec![2, 0, 5, 3],
+            prefetch_targets(&photos, 2, Direction::Backward, 3)
+        );
+        // Rated photos can be current, but aren't prefetched
+        assert_eq!(
+            vec![1, 2],
+            prefetch_targets(&photos, 1, Direction::Forward, 1)
+        );
+        // Wrapping around stops before the photos repeat
+        assert_eq!(
+            vec![3, 5, 0, 2],
+            prefetch_targets(&photos, 3, Direction::Forward, 10)
+        );
+        assert!(prefetch_targets(&[], 0, Direction::Forward, 3).is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+/// Lowercase extensions of the RAW formats we pair with processed images.
+pub const RAW_EXTENSIONS: [&str; 17] = [
+    "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "iiq", "mrw", "nef", "nrw", "orf", "pef",
+    "raf", "rw2", "sr2", "srw",
+];
+
+pub fn is_raw_extension(extension: &OsStr) -> bool {
+    let extension = extension.to_string_lossy().to_lowercase();
+    RAW_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Index of the RAW files found in one folder, keyed by file stem so a processed image can
+/// look up its companion without touching the disk again.
+#[derive(Default)]
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))] // only the native folder scan pairs files
+pub struct RawCompanions {
+    by_stem: HashMap<OsString, PathBuf>,
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+impl RawCompanions {
+    pub fn from_paths<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut by_stem = HashMap::new();
+        for path in paths {
+            let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+                continue;
+            };
+            if !is_raw_extension(extension) {
+                continue;
+            }
+            if let Some(previous) = by_stem.insert(stem.to_owned(), path.to_path_buf()) {
+                log::warn!(
+                    "Found several RAW files for {:?}, pairing {:?} instead of {:?}",
+                    stem,
+                    path,
+                    previous
+                );
+            }
+        }
+        Self { by_stem }
+    }
+
+    /// The RAW file sitting next to `processed_path` with the same stem, if there is one.
+    pub fn companion_for(&self, processed_path: &Path) -> Option<PathBuf> {
+        let stem = processed_path.file_stem()?;
+        self.by_stem.get(stem).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_companion_for_ignores_extension_case() {
+        let paths = [
+            PathBuf::from("/shoot/DSC0001.JPG"),
+            PathBuf::from("/shoot/DSC0001.ARW"),
+            PathBuf::from("/shoot/IMG_0002.jpg"),
+            PathBuf::from("/shoot/IMG_0002.Cr3"),
+            PathBuf::from("/shoot/DSCF0003.jpg"),
+            PathBuf::from("/shoot/DSCF0003.xmp"),
+        ];
+        let companions = RawCompanions::from_paths(paths.iter().map(PathBuf::as_path));
+
+        assert_eq!(
+            Some(PathBuf::from("/shoot/DSC0001.ARW")),
+            companions.companion_for(&paths[0])
+        );
+        assert_eq!(
+            Some(PathBuf::from("/shoot/IMG_0002.Cr3")),
+            companions.companion_for(&paths[2])
+        );
+        assert_eq!(None, companions.companion_for(&paths[4]));
+    }
+}
@@ -0,0 +1,198 @@
+//! Pulls the full-size JPEG preview cameras embed into their RAW files, which lets us cull
+//! RAW-only shoots without developing the sensor data ourselves.
+
+use std::{cmp::Reverse, ops::Range};
+
+use super::tiff::{
+    IfdEntry, Tiff, TAG_COMPRESSION, TAG_JPEG_INTERCHANGE_FORMAT,
+    TAG_JPEG_INTERCHANGE_FORMAT_LENGTH, TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS,
+};
+
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW ";
+const RAF_JPEG_OFFSET_POSITION: usize = 84;
+const RAF_JPEG_LENGTH_POSITION: usize = 88;
+
+/// TIFF compression values used for JPEG compressed strips.
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+
+/// Returns the largest JPEG embedded in a RAF or TIFF based RAW file (ARW, NEF, DNG, CR2, ...).
+pub fn extract_jpeg_preview(raw: &[u8]) -> Option<&[u8]> {
+    preview_ranges(raw)
+        .into_iter()
+        .filter_map(|range| raw.get(range))
+        .find(|jpeg| is_decodable_jpeg(jpeg))
+}
+
+/// Where the JPEGs embedded in a RAW file may be, largest first. Only the headers are looked
+/// at, so `head` can be the start of the file as long as they are in it.
+pub fn preview_ranges(head: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = match head.starts_with(RAF_MAGIC) {
+        // Fujifilm keeps the preview offset and length in a fixed place of its own header
+        true => raf_preview_position(head)
+            .and_then(|(offset, length)| range_at(offset, length))
+            .into_iter()
+            .collect(),
+        false => tiff_preview_ranges(head),
+    };
+    ranges.sort_by_key(|range| Reverse(range.len()));
+    ranges
+}
+
+/// As much of the JPEG preview of a RAF as `raw` holds, which may only be the start of the
+/// file. Enough for the EXIF block at the beginning of the preview.
+pub fn raf_preview_start(raw: &[u8]) -> Option<&[u8]> {
+    if !raw.starts_with(RAF_MAGIC) {
+        return None;
+    }
+    let (offset, length) = raf_preview_position(raw)?;
+    raw.get(offset..offset.checked_add(length)?.min(raw.len()))
+}
+
+fn raf_preview_position(raw: &[u8]) -> Option<(usize, usize)> {
+    let read_u32 = |position: usize| -> Option<usize> {
+        let bytes: [u8; 4] = raw.get(position..position + 4)?.try_into().ok()?;
+        Some(u32::from_be_bytes(bytes) as usize)
+    };
+    Some((
+        read_u32(RAF_JPEG_OFFSET_POSITION)?,
+        read_u32(RAF_JPEG_LENGTH_POSITION)?,
+    ))
+}
+
+fn tiff_preview_ranges(head: &[u8]) -> Vec<Range<usize>> {
+    let Some(tiff) = Tiff::parse(head) else {
+        return Vec::new();
+    };
+    let mut ranges = Vec::new();
+    let range_of = |offset: &IfdEntry, length: &IfdEntry| {
+        range_at(tiff.entry_u32(offset)?, tiff.entry_u32(length)?)
+    };
+
+    for ifd in tiff.all_ifds() {
+        let interchange = ifd
+            .get(TAG_JPEG_INTERCHANGE_FORMAT)
+            .zip(ifd.get(TAG_JPEG_INTERCHANGE_FORMAT_LENGTH));
+        if let Some(range) = interchange.and_then(|(offset, length)| range_of(offset, length)) {
+            ranges.push(range);
+        }
+
+        // Canon and DNG store previews as a single JPEG compressed strip
+        let compression = ifd
+            .get(TAG_COMPRESSION)
+            .and_then(|entry| tiff.entry_u32(entry));
+        if !matches!(compression, Some(COMPRESSION_OLD_JPEG | COMPRESSION_JPEG)) {
+            continue;
+        }
+        let strips = ifd
+            .get(TAG_STRIP_OFFSETS)
+            .zip(ifd.get(TAG_STRIP_BYTE_COUNTS))
+            .filter(|(offsets, _)| offsets.count == 1);
+        if let Some(range) =
+            strips.and_then(|(offsets, byte_counts)| range_of(offsets, byte_counts))
+        {
+            ranges.push(range);
+        }
+    }
+    ranges
+}
+
+fn range_at(offset: impl TryInto<usize>, length: impl TryInto<usize>) -> Option<Range<usize>> {
+    let offset = offset.try_into().ok()?;
+    Some(offset..offset.checked_add(length.try_into().ok()?)?)
+}
+
+/// Lossless JPEG (used for the sensor data of CR2 and DNG) looks like a preview but the image
+/// crate can't decode it, so we only accept baseline and progressive frames.
+pub fn is_decodable_jpeg(jpeg: &[u8]) -> bool {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut position = 2;
+    while let Some(&[0xFF, marker]) = jpeg.get(position..position + 2) {
+        match marker {
+            0xC0..=0xC2 => return true,
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA | 0xD9 => return false,
+            _ => {}
+        }
+        let Some(&[high, low]) = jpeg.get(position + 2..position + 4) else {
+            return false;
+        };
+        position += 2 + u16::from_be_bytes([high, low]) as usize;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_jpeg() -> Vec<u8> {
+        std::fs::read("assets/samples/1.jpg").unwrap()
+    }
+
+    /// A little endian TIFF with one IFD pointing at a small thumbnail and one SubIFD pointing
+    /// at the full-size preview, which is roughly what a NEF looks like.
+    fn build_tiff(thumbnail: &[u8], preview: &[u8]) -> Vec<u8> {
+        let ifd0_offset = 8u32;
+        let sub_ifd_offset = ifd0_offset + 2 + 3 * 12 + 4;
+        let thumbnail_offset = sub_ifd_offset + 2 + 2 * 12 + 4;
+        let preview_offset = thumbnail_offset + thumbnail.len() as u32;
+
+        let entry = |tag: u16, field_type: u16, value: u32| {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&field_type.to_le_bytes());
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes
+        };
+
+        let mut tiff = b"II\x2A\x00".to_vec();
+        tiff.extend_from_slice(&ifd0_offset.to_le_bytes());
+
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend(entry(0x014A, 4, sub_ifd_offset));
+        tiff.extend(entry(0x0201, 4, thumbnail_offset));
+        tiff.extend(entry(0x0202, 4, thumbnail.len() as u32));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        tiff.extend(entry(0x0201, 4, preview_offset));
+        tiff.extend(entry(0x0202, 4, preview.len() as u32));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+
+        tiff.extend_from_slice(thumbnail);
+        tiff.extend_from_slice(preview);
+        tiff
+    }
+
+    #[test]
+    fn test_extract_tiff_preview_picks_largest_jpeg() {
+        let preview = sample_jpeg();
+        let thumbnail = &preview[..preview.len() / 4];
+        let raw = build_tiff(thumbnail, &preview);
+
+        assert_eq!(Some(preview.as_slice()), extract_jpeg_preview(&raw));
+    }
+
+    #[test]
+    fn test_extract_raf_preview() {
+        let preview = sample_jpeg();
+        let mut raw = RAF_MAGIC.to_vec();
+        raw.resize(100, 0);
+        raw[RAF_JPEG_OFFSET_POSITION..RAF_JPEG_OFFSET_POSITION + 4]
+            .copy_from_slice(&100u32.to_be_bytes());
+        raw[RAF_JPEG_LENGTH_POSITION..RAF_JPEG_LENGTH_POSITION + 4]
+            .copy_from_slice(&(preview.len() as u32).to_be_bytes());
+        raw.extend_from_slice(&preview);
+
+        assert_eq!(Some(preview.as_slice()), extract_jpeg_preview(&raw));
+    }
+
+    #[test]
+    fn test_extract_preview_rejects_garbage() {
+        assert_eq!(None, extract_jpeg_preview(b"not a raw file at all"));
+        assert_eq!(None, extract_jpeg_preview(b"II\x2A\x00\xFF\xFF\xFF\xFF"));
+    }
+}
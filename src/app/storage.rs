@@ -0,0 +1,345 @@
+//! The culling progress of a folder, kept in `.blitz/storage.ron`. The photo list is wrapped
+//! in an envelope with the format version, so files written by older versions of blitz can be
+//! migrated and files blitz can't read are set aside instead of crashing the app. Saves replace
+//! the file atomically and the previous versions are kept as rotating backups next to it.
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use ron::ser::PrettyConfig;
+
+use super::models::ImageInfo;
+
+/// Bump this when the envelope changes, and keep the previous format around as a
+/// `StorageVn` with a `From` into the next one, see `migrate`.
+pub const STORAGE_VERSION: u32 = 1;
+
+/// How many older versions of the culling progress are kept, `storage.backup-1.ron` being the
+/// newest.
+const BACKUP_COUNT: usize = 5;
+
+/// Autosaves happen every few seconds, backups that close together would all hold the same
+/// mistake.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Folder specific choices that don't belong into the app wide settings.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub struct FolderSettings {
+    pub wheat_dir: Option<PathBuf>,
+    pub chaffe_dir: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct StorageEnvelope {
+    pub format_version: u32,
+    /// The blitz version that wrote the file, to make sense of bug reports.
+    pub app_version: String,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    pub saved_at: u64,
+    #[serde(default)]
+    pub settings: FolderSettings,
+    pub photos: Vec<ImageInfo>,
+}
+
+/// Format 0, the bare photo list blitz wrote before the envelope existed.
+struct StorageV0(Vec<ImageInfo>);
+
+impl From<StorageV0> for StorageEnvelope {
+    fn from(StorageV0(photos): StorageV0) -> Self {
+        Self {
+            format_version: STORAGE_VERSION,
+            app_version: String::new(),
+            created_at: 0,
+            saved_at: 0,
+            settings: FolderSettings::default(),
+            photos,
+        }
+    }
+}
+
+/// Reads just the version, every format since the envelope starts with it.
+#[derive(serde::Deserialize)]
+struct VersionProbe {
+    format_version: u32,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))] // only native builds read from disk
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    /// Written by a newer blitz that knows a format this one doesn't.
+    NewerVersion(u32),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(err) => write!(f, "couldn't read the culling progress: {}", err),
+            StorageError::Parse(err) => write!(f, "the culling progress is damaged: {}", err),
+            StorageError::NewerVersion(version) => write!(
+                f,
+                "the culling progress was saved by a newer blitz (format {})",
+                version
+            ),
+        }
+    }
+}
+
+impl From<ron::error::SpannedError> for StorageError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        StorageError::Parse(err)
+    }
+}
+
+pub fn path(photo_dir: &Path) -> PathBuf {
+    let mut storage_path = photo_dir.to_path_buf();
+    storage_path.push(".blitz");
+    storage_path.push("storage.ron");
+    storage_path
+}
+
+/// Reads the culling progress of `photo_dir`, `None` when the folder was never opened before.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(photo_dir: &Path) -> Result<Option<StorageEnvelope>, StorageError> {
+    match fs::read(path(photo_dir)) {
+        Ok(serialized_ron) => migrate(&serialized_ron).map(Some),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(StorageError::Io(err)),
+    }
+}
+
+/// Brings a file of any known format up to the current one.
+fn migrate(serialized_ron: &[u8]) -> Result<StorageEnvelope, StorageError> {
+    let version =
+        ron::de::from_bytes::<VersionProbe>(serialized_ron).map_or(0, |probe| probe.format_version);
+    match version {
+        0 => Ok(StorageV0(ron::de::from_bytes(serialized_ron)?).into()),
+        STORAGE_VERSION => Ok(ron::de::from_bytes(serialized_ron)?),
+        newer => Err(StorageError::NewerVersion(newer)),
+    }
+}
+
+/// Writes the photos and folder settings, keeping the creation time of an existing file.
+pub fn save(photo_dir: &Path, photos: &[ImageInfo], settings: &FolderSettings) -> io::Result<()> {
+    if let Err(err) = rotate_backups(photo_dir, BACKUP_INTERVAL) {
+        log::warn!("Couldn't back up the culling progress: {}", err);
+    }
+
+    let storage_path = path(photo_dir);
+    if let Some(blitz_dir) = storage_path.parent() {
+        fs::create_dir_all(blitz_dir)?;
+    }
+
+    let now = unix_seconds(SystemTime::now());
+    let created_at = fs::read(&storage_path)
+        .ok()
+        .and_then(|serialized_ron| migrate(&serialized_ron).ok())
+        .map(|previous| previous.created_at)
+        .filter(|created_at| *created_at > 0)
+        .unwrap_or(now);
+    let envelope = StorageEnvelope {
+        format_version: STORAGE_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at,
+        saved_at: now,
+        settings: settings.clone(),
+        photos: photos.to_vec(),
+    };
+
+    let ron_str =
+        ron::ser::to_string_pretty(&envelope, PrettyConfig::new()).map_err(io::Error::other)?;
+    write_atomically(&storage_path, ron_str.as_bytes())
+}
+
+/// Writes to a temporary file next to `path` and renames it over `path`, so a crash or power
+/// cut leaves either the old or the new file behind, never a truncated one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // The disk cache writes its entries from several threads at once
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let temp_path = path.with_extension(format!(
+        "tmp-{}-{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+fn backup_path(photo_dir: &Path, number: usize) -> PathBuf {
+    path(photo_dir).with_file_name(format!("storage.backup-{}.ron", number))
+}
+
+/// Shifts the backups one place and copies the current file in as the newest one, unless the
+/// newest backup is younger than `min_age`.
+fn rotate_backups(photo_dir: &Path, min_age: Duration) -> io::Result<()> {
+    let storage_path = path(photo_dir);
+    if !storage_path.exists() {
+        return Ok(());
+    }
+    let newest_backup = backup_path(photo_dir, 1);
+    let is_recent = fs::metadata(&newest_backup)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < min_age);
+    if is_recent {
+        return Ok(());
+    }
+
+    for number in (1..BACKUP_COUNT).rev() {
+        let backup = backup_path(photo_dir, number);
+        if backup.exists() {
+            fs::rename(backup, backup_path(photo_dir, number + 1))?;
+        }
+    }
+    fs::copy(storage_path, newest_backup).map(|_| ())
+}
+
+/// The newest backup that can still be read, used when the file itself can't.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_newest_backup(photo_dir: &Path) -> Option<(PathBuf, StorageEnvelope)> {
+    (1..=BACKUP_COUNT)
+        .map(|number| backup_path(photo_dir, number))
+        .find_map(|backup| {
+            let envelope = migrate(&fs::read(&backup).ok()?).ok()?;
+            Some((backup, envelope))
+        })
+}
+
+/// Moves a file that couldn't be loaded out of the way, e.g. to
+/// `.blitz/storage.unreadable-1718000000.ron`, so the folder can start over without losing it.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn back_up_unreadable(photo_dir: &Path) -> io::Result<PathBuf> {
+    let storage_path = path(photo_dir);
+    let backup_path = storage_path.with_file_name(format!(
+        "storage.unreadable-{}.ron",
+        unix_seconds(SystemTime::now())
+    ));
+    fs::rename(&storage_path, &backup_path)?;
+    Ok(backup_path)
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::Rating;
+    use crate::app::test_dir::TestDir;
+
+    fn photo() -> ImageInfo {
+        ImageInfo {
+            path_processed: PathBuf::from("/card/1.jpg"),
+            image_name: "1.jpg".to_string(),
+            rating: Rating::Approve,
+            stars: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip_keeps_settings_and_creation_time() {
+        let photo_dir = TestDir::new("storage_round_trip");
+        fs::create_dir_all(photo_dir.join(".blitz")).unwrap();
+        let settings = FolderSettings {
+            wheat_dir: Some(PathBuf::from("/nas/wheat")),
+            chaffe_dir: None,
+        };
+
+        save(&photo_dir, &[photo()], &settings).unwrap();
+        let first = load(&photo_dir).unwrap().unwrap();
+        save(&photo_dir, &[photo()], &settings).unwrap();
+        let second = load(&photo_dir).unwrap().unwrap();
+
+        assert_eq!(STORAGE_VERSION, second.format_version);
+        assert_eq!(env!("CARGO_PKG_VERSION"), second.app_version);
+        assert_eq!(first.created_at, second.created_at);
+        assert_eq!(settings, second.settings);
+        assert_eq!(Rating::Approve, second.photos[0].rating);
+        assert_eq!(3, second.photos[0].stars);
+    }
+
+    #[test]
+    fn test_bare_photo_list_is_migrated() {
+        let photo_dir = TestDir::new("storage_v0");
+        fs::create_dir_all(photo_dir.join(".blitz")).unwrap();
+        let legacy = ron::ser::to_string_pretty(&vec![photo()], PrettyConfig::new()).unwrap();
+        fs::write(path(&photo_dir), legacy).unwrap();
+
+        let envelope = load(&photo_dir).unwrap().unwrap();
+        assert_eq!(STORAGE_VERSION, envelope.format_version);
+        assert_eq!(1, envelope.photos.len());
+        assert_eq!(Rating::Approve, envelope.photos[0].rating);
+    }
+
+    #[test]
+    fn test_backups_rotate() {
+        let photo_dir = TestDir::new("storage_backups");
+        fs::create_dir_all(photo_dir.join(".blitz")).unwrap();
+        for stars in 0..=BACKUP_COUNT as u8 + 1 {
+            let photo = ImageInfo { stars, ..photo() };
+            rotate_backups(&photo_dir, Duration::ZERO).unwrap();
+            save(&photo_dir, &[photo], &FolderSettings::default()).unwrap();
+        }
+
+        // Only the saved file and the backups are left, no temporary files
+        assert_eq!(
+            BACKUP_COUNT + 1,
+            fs::read_dir(photo_dir.join(".blitz")).unwrap().count()
+        );
+        let (newest_backup, envelope) = load_newest_backup(&photo_dir).unwrap();
+        assert_eq!(backup_path(&photo_dir, 1), newest_backup);
+        assert_eq!(BACKUP_COUNT as u8, envelope.photos[0].stars);
+
+        fs::write(path(&photo_dir), "damaged").unwrap();
+        fs::write(backup_path(&photo_dir, 1), "damaged too").unwrap();
+        let (older_backup, envelope) = load_newest_backup(&photo_dir).unwrap();
+        assert_eq!(backup_path(&photo_dir, 2), older_backup);
+        assert_eq!(BACKUP_COUNT as u8 - 1, envelope.photos[0].stars);
+    }
+
+    #[test]
+    fn test_unreadable_files_are_backed_up() {
+        let photo_dir = TestDir::new("storage_unreadable");
+        fs::create_dir_all(photo_dir.join(".blitz")).unwrap();
+        fs::write(path(&photo_dir), "[(path_processed: 1").unwrap();
+        assert!(matches!(load(&photo_dir), Err(StorageError::Parse(_))));
+
+        let backup_path = back_up_unreadable(&photo_dir).unwrap();
+        assert!(!path(&photo_dir).exists());
+        assert_eq!(
+            "[(path_processed: 1",
+            fs::read_to_string(backup_path).unwrap()
+        );
+        assert!(load(&photo_dir).unwrap().is_none());
+
+        fs::write(
+            path(&photo_dir),
+            "(format_version: 99, photos: [], future_field: true)",
+        )
+        .unwrap();
+        assert!(matches!(
+            load(&photo_dir),
+            Err(StorageError::NewerVersion(99))
+        ));
+    }
+}
@@ -0,0 +1,39 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty folder under the system temp dir that is removed again when the
+/// test ends, even if it panics.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Names must be unique per test, as tests run in parallel.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("blitz_test_{name}"));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,162 @@
+//! Keeps the textures of at most `max_texture_count` photos. Once there are more, the least
+//! recently shown photos are forgotten, except for the ones on screen, the neighbors of the
+//! current photo and the ones being prefetched, which are pinned. Forgetting drops the bytes,
+//! the decoded pixels and the texture egui's loaders keep for the photo.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{tiers::Tier, BlitzApp};
+
+/// Photos this close to the current one are pinned, so going back and forth is instant.
+const PINNED_NEIGHBORS: usize = 5;
+
+/// Counted once per appearance on screen rather than per frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Photos that came back on screen while still loaded.
+    pub hits: u64,
+    /// Photos that had to be loaded to be shown.
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Default)]
+pub struct TextureCache {
+    /// The pass each photo was last shown in, keyed by its image URI.
+    last_shown: HashMap<String, u64>,
+    pub stats: CacheStats,
+}
+
+impl TextureCache {
+    pub fn len(&self) -> usize {
+        self.last_shown.len()
+    }
+
+    /// Records that `uri` is on screen during `pass`.
+    pub fn touch(&mut self, uri: String, pass: u64) {
+        match self.last_shown.insert(uri, pass) {
+            None => self.stats.misses += 1,
+            Some(shown) if shown + 1 < pass => self.stats.hits += 1,
+            Some(_) => {}
+        }
+    }
+
+    /// Records that `uri` was loaded ahead of being shown, which counts neither as a hit nor
+    /// as a miss until it is.
+    pub fn prefetched(&mut self, uri: String, pass: u64) {
+        self.last_shown.entry(uri).or_insert(pass);
+    }
+
+    /// Whether `uri` has been shown or prefetched and not forgotten since.
+    pub fn contains(&self, uri: &str) -> bool {
+        self.last_shown.contains_key(uri)
+    }
+
+    /// Takes out the least recently shown photos until at most `capacity` are left and returns
+    /// them. Pinned photos and the ones shown during `pass` stay, even if that's more.
+    pub fn evict(&mut self, capacity: usize, pinned: &HashSet<String>, pass: u64) -> Vec<String> {
+        let excess = self.last_shown.len().saturating_sub(capacity);
+        if excess == 0 {
+            return Vec::new();
+        }
+        let mut candidates: Vec<(&String, u64)> = self
+            .last_shown
+            .iter()
+            .filter(|(uri, shown)| **shown < pass && !pinned.contains(*uri))
+            .map(|(uri, shown)| (uri, *shown))
+            .collect();
+        candidates.sort_by_key(|(_, shown)| *shown);
+        let evicted: Vec<String> = candidates
+            .into_iter()
+            .take(excess)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+
+        for uri in &evicted {
+            self.last_shown.remove(uri);
+        }
+        self.stats.evictions += evicted.len() as u64;
+        evicted
+    }
+
+    pub fn clear(&mut self) {
+        self.last_shown.clear();
+    }
+}
+
+/// The URIs of the photos shown during the current pass.
+#[derive(Clone, Default)]
+struct ShownUris(Vec<String>);
+
+/// Records that the image behind `uri` is on screen, see [`BlitzApp::evict_textures`].
+pub fn mark_shown(ctx: &egui::Context, uri: String) {
+    ctx.data_mut(|data| {
+        data.get_temp_mut_or_default::<ShownUris>(egui::Id::NULL)
+            .0
+            .push(uri)
+    });
+}
+
+impl BlitzApp {
+    /// Forgets the least recently shown photos beyond `max_texture_count`.
+    pub fn evict_textures(&mut self, ctx: &egui::Context) {
+        let pass = ctx.cumulative_pass_nr();
+        let shown = ctx
+            .data_mut(|data| data.remove_temp::<ShownUris>(egui::Id::NULL))
+            .unwrap_or_default();
+        for uri in shown.0 {
+            self.texture_cache.touch(uri, pass);
+        }
+
+        let Ok(photos) = self.photos.try_read() else {
+            return;
+        };
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut pinned: HashSet<String> = photos
+            .iter()
+            .skip(self.photos_index.saturating_sub(PINNED_NEIGHBORS))
+            .take(2 * PINNED_NEIGHBORS + 1)
+            .flat_map(|photo| Tier::ALL.map(|tier| photo.image_uri(tier)))
+            .collect();
+        // Unrated photos ahead can be further away, evicting them would only have them
+        // prefetched again
+        #[cfg(not(target_arch = "wasm32"))]
+        pinned.extend(
+            self.prefetched_photos(&photos)
+                .into_iter()
+                .map(|photo| photo.image_uri(Tier::Screen)),
+        );
+        for uri in self
+            .texture_cache
+            .evict(self.max_texture_count, &pinned, pass)
+        {
+            ctx.forget_image(&uri);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_shown_first() {
+        let mut cache = TextureCache::default();
+        for (pass, uri) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            cache.touch(uri.to_string(), pass as u64);
+        }
+        // Back on screen, so no longer the oldest
+        cache.touch("a".to_string(), 10);
+        assert_eq!(1, cache.stats.hits);
+        assert_eq!(4, cache.stats.misses);
+
+        let pinned = HashSet::from(["b".to_string()]);
+        assert_eq!(vec!["c".to_string()], cache.evict(3, &pinned, 11));
+        assert_eq!(Vec::<String>::new(), cache.evict(3, &pinned, 11));
+
+        // What is on screen stays, even over capacity
+        assert_eq!(vec!["d".to_string()], cache.evict(0, &pinned, 10));
+        assert_eq!(2, cache.len());
+        assert_eq!(2, cache.stats.evictions);
+    }
+}
@@ -0,0 +1,426 @@
+//! Photos are decoded at the size they are shown at. The queue gets thumbnails, usually the
+//! one the camera put into the EXIF block, the center panel gets the photo scaled to fit the
+//! monitor and only the loupe decodes every pixel of it.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use egui::{
+    load::{BytesPoll, ImageLoadResult, ImageLoader, ImagePoll, LoadError, SizeHint},
+    ColorImage,
+};
+use image::{metadata::Orientation, DynamicImage};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::{disk_cache::DiskCache, photo_loader, worker_pool::WorkerPool};
+use super::{exif, orientation};
+
+/// Appended to image URIs that are decoded smaller than the full resolution.
+const TIER_URI_MARKER: &str = "#tier=";
+
+/// The longest edge of a thumbnail in pixels, enough for the 100 point wide queue on a HiDPI
+/// screen.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// The longest edge of a screen-fit image when the monitor size isn't known.
+const FALLBACK_SCREEN_SIZE: u32 = 2560;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tier {
+    Thumbnail,
+    /// Scaled down to fit the monitor.
+    Screen,
+    Full,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 3] = [Tier::Thumbnail, Tier::Screen, Tier::Full];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Thumbnail => "thumbnail",
+            Tier::Screen => "screen",
+            Tier::Full => "full",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|tier| tier.name() == name)
+    }
+}
+
+/// Marks `uri` to be decoded at the size of `tier`, the full resolution keeps the plain URI.
+pub fn tier_uri(uri: String, tier: Tier) -> String {
+    match tier {
+        Tier::Full => uri,
+        _ => format!("{}{}{}", uri, TIER_URI_MARKER, tier.name()),
+    }
+}
+
+/// Splits `uri` into the URI before [`tier_uri`] and the tier it added.
+pub fn split_tier(uri: &str) -> (&str, Option<Tier>) {
+    match uri.rsplit_once(TIER_URI_MARKER) {
+        Some((rest, name)) => match Tier::from_name(name) {
+            Some(tier) => (rest, Some(tier)),
+            None => (uri, None),
+        },
+        None => (uri, None),
+    }
+}
+
+/// The longest edge of the monitor egui runs on, in pixels.
+pub fn screen_size(ctx: &egui::Context) -> u32 {
+    ctx.input(|input| {
+        input
+            .viewport()
+            .monitor_size
+            .map(|size| (size.max_elem() * input.pixels_per_point) as u32)
+    })
+    .filter(|size| *size > 0)
+    .unwrap_or(FALLBACK_SCREEN_SIZE)
+}
+
+/// Decodes `data` scaled down to `tier`, still in the orientation of the file. Thumbnails come
+/// from the EXIF thumbnail if there is one, `screen_size` bounds the screen-fit image.
+pub fn scale_down(
+    data: &[u8],
+    tier: Tier,
+    screen_size: u32,
+) -> Result<DynamicImage, image::ImageError> {
+    let max_size = match tier {
+        Tier::Thumbnail => THUMBNAIL_SIZE,
+        Tier::Screen => screen_size,
+        Tier::Full => u32::MAX,
+    };
+    let exif_thumbnail = match tier {
+        Tier::Thumbnail => {
+            exif::find_exif_thumbnail(data).and_then(|jpeg| image::load_from_memory(jpeg).ok())
+        }
+        _ => None,
+    };
+    let mut image = match exif_thumbnail {
+        Some(image) => image,
+        None => image::load_from_memory(data)?,
+    };
+    if image.width().max(image.height()) > max_size {
+        image = image.thumbnail(max_size, max_size);
+    }
+    Ok(image)
+}
+
+/// Decodes `data` for `tier` in `orientation`. `scaled` is handed the image of the lower tiers
+/// before it's turned, which is what the disk cache keeps.
+fn decode(
+    data: &[u8],
+    tier: Tier,
+    orientation: Orientation,
+    screen_size: u32,
+    scaled: impl FnOnce(&DynamicImage),
+) -> Result<ColorImage, image::ImageError> {
+    if tier == Tier::Full {
+        return orientation::create_image(data, orientation);
+    }
+    let image = scale_down(data, tier, screen_size)?;
+    scaled(&image);
+    Ok(orientation::to_color_image(image, orientation))
+}
+
+#[derive(Clone)]
+enum Entry {
+    /// Being looked up in the disk cache on a worker.
+    #[cfg(not(target_arch = "wasm32"))]
+    Looking,
+    /// Not in the disk cache, decoded from the bytes of the photo instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    Missed,
+    /// Being decoded from the bytes of the photo on a worker.
+    #[cfg(not(target_arch = "wasm32"))]
+    Decoding,
+    Decoded(Result<Arc<ColorImage>, LoadError>),
+}
+
+/// Decodes the URIs built by [`tier_uri`] and [`orientation::oriented_uri`] at the size of
+/// their tier with their orientation applied. Plain URIs are left to the `egui_extras`
+/// loaders, this one is registered after them so egui asks it first.
+#[cfg_attr(target_arch = "wasm32", derive(Default))]
+pub struct TieredImageLoader {
+    cache: Arc<Mutex<HashMap<String, Entry>>>,
+    /// Where the scaled down tiers of `photo://` URIs are kept between sessions.
+    #[cfg(not(target_arch = "wasm32"))]
+    disk_cache: Arc<DiskCache>,
+    /// Look up the disk cache and decode, both take longer than a frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    workers: WorkerPool,
+}
+
+impl TieredImageLoader {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(disk_cache: Arc<DiskCache>, workers: WorkerPool) -> Self {
+        Self {
+            cache: Default::default(),
+            disk_cache,
+            workers,
+        }
+    }
+
+    /// Decodes `bytes` loaded from `source` on a worker, keeping the lower tiers of photos in
+    /// the disk cache.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[allow(clippy::too_many_arguments)]
+    fn decode_on_worker(
+        &self,
+        ctx: &egui::Context,
+        uri: &str,
+        source: &str,
+        bytes: egui::load::Bytes,
+        tier: Tier,
+        orientation: Orientation,
+        screen_size: u32,
+    ) {
+        let (uri, path) = (uri.to_string(), photo_loader::photo_path(source));
+        let (worker_cache, worker_ctx) = (self.cache.clone(), ctx.clone());
+        let disk_cache = self.disk_cache.clone();
+        self.workers.execute(move || {
+            // Forgotten while waiting for a worker, e.g. because another folder was opened
+            if !worker_cache.lock().unwrap().contains_key(&uri) {
+                return;
+            }
+            let result = decode(&bytes, tier, orientation, screen_size, |image| {
+                if let Some(path) = path {
+                    disk_cache.store_in_background(path, tier, screen_size, image.clone());
+                }
+            })
+            .map(Arc::new)
+            .map_err(|err| LoadError::Loading(err.to_string()));
+            if let Some(cached) = worker_cache.lock().unwrap().get_mut(&uri) {
+                *cached = Entry::Decoded(result);
+            }
+            worker_ctx.request_repaint();
+        });
+    }
+
+    /// Looks for `uri` in the disk cache on a worker, when a previous session may have
+    /// decoded it already. `false` for the URIs that are never cached.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn look_up(
+        &self,
+        ctx: &egui::Context,
+        uri: &str,
+        source: &str,
+        tier: Tier,
+        orientation: Orientation,
+        screen_size: u32,
+    ) -> bool {
+        let Some(path) = photo_loader::photo_path(source) else {
+            return false;
+        };
+        if tier == Tier::Full {
+            return false;
+        }
+
+        let uri = uri.to_string();
+        let (worker_cache, worker_ctx) = (self.cache.clone(), ctx.clone());
+        let disk_cache = self.disk_cache.clone();
+        self.workers.execute(move || {
+            // Forgotten while waiting for a worker, e.g. because another folder was opened
+            if !worker_cache.lock().unwrap().contains_key(&uri) {
+                return;
+            }
+            let entry = match disk_cache.load(&path, tier, screen_size) {
+                Some(image) => {
+                    let image = orientation::to_color_image(image, orientation);
+                    Entry::Decoded(Ok(Arc::new(image)))
+                }
+                None => Entry::Missed,
+            };
+            if let Some(cached) = worker_cache.lock().unwrap().get_mut(&uri) {
+                *cached = entry;
+            }
+            worker_ctx.request_repaint();
+        });
+        true
+    }
+}
+
+impl ImageLoader for TieredImageLoader {
+    fn id(&self) -> &str {
+        egui::generate_loader_id!(TieredImageLoader)
+    }
+
+    #[cfg_attr(target_arch = "wasm32", allow(unused_mut, unused_variables))]
+    fn load(&self, ctx: &egui::Context, uri: &str, _: SizeHint) -> ImageLoadResult {
+        let (rest, orientation) = orientation::split_orientation(uri);
+        let (source, tier) = split_tier(rest);
+        if orientation.is_none() && tier.is_none() {
+            return Err(LoadError::NotSupported);
+        }
+        let tier = tier.unwrap_or(Tier::Full);
+        let orientation = orientation.unwrap_or(Orientation::NoTransforms);
+        let screen_size = screen_size(ctx);
+
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(uri).cloned() {
+            Some(Entry::Decoded(result)) => return result.map(|image| ImagePoll::Ready { image }),
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(Entry::Looking | Entry::Decoding) => return Ok(ImagePoll::Pending { size: None }),
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(Entry::Missed) => {}
+            None =>
+            {
+                #[cfg(not(target_arch = "wasm32"))]
+                if self.look_up(ctx, uri, source, tier, orientation, screen_size) {
+                    cache.insert(uri.to_string(), Entry::Looking);
+                    return Ok(ImagePoll::Pending { size: None });
+                }
+            }
+        }
+
+        match ctx.try_load_bytes(uri) {
+            #[cfg(not(target_arch = "wasm32"))]
+            Ok(BytesPoll::Ready { bytes, .. }) => {
+                cache.insert(uri.to_string(), Entry::Decoding);
+                self.decode_on_worker(ctx, uri, source, bytes, tier, orientation, screen_size);
+                Ok(ImagePoll::Pending { size: None })
+            }
+            // There are no threads on the web, but the other loaders can have the cache meanwhile
+            #[cfg(target_arch = "wasm32")]
+            Ok(BytesPoll::Ready { bytes, .. }) => {
+                drop(cache);
+                let result = decode(&bytes, tier, orientation, screen_size, |_| {})
+                    .map(Arc::new)
+                    .map_err(|err| LoadError::Loading(err.to_string()));
+                let decoded = Entry::Decoded(result.clone());
+                self.cache.lock().unwrap().insert(uri.to_string(), decoded);
+                result.map(|image| ImagePoll::Ready { image })
+            }
+            Ok(BytesPoll::Pending { size }) => Ok(ImagePoll::Pending { size }),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn forget(&self, uri: &str) {
+        self.cache.lock().unwrap().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.cache
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| match entry {
+                Entry::Decoded(Ok(image)) => {
+                    image.pixels.len() * std::mem::size_of::<egui::Color32>()
+                }
+                Entry::Decoded(Err(err)) => err.byte_size(),
+                #[cfg(not(target_arch = "wasm32"))]
+                Entry::Looking | Entry::Missed | Entry::Decoding => 0,
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+
+    #[test]
+    fn test_tier_uri() {
+        let uri = || "photo:///shoot/IMG_0001.JPG".to_string();
+        assert_eq!(uri(), tier_uri(uri(), Tier::Full));
+        for tier in [Tier::Thumbnail, Tier::Screen] {
+            let oriented = orientation::oriented_uri(tier_uri(uri(), tier), Orientation::Rotate90);
+            let (rest, _) = orientation::split_orientation(&oriented);
+            assert_eq!((uri().as_str(), Some(tier)), split_tier(rest));
+        }
+        assert_eq!((uri().as_str(), None), split_tier(&uri()));
+    }
+
+    #[test]
+    fn test_scale_down_sizes() {
+        let data = std::fs::read("assets/samples/1.jpg").unwrap();
+        let full = scale_down(&data, Tier::Full, 1000).unwrap();
+        let screen = scale_down(&data, Tier::Screen, 1000).unwrap();
+        let thumbnail = scale_down(&data, Tier::Thumbnail, 1000).unwrap();
+
+        assert!(full.width() > 1000);
+        assert_eq!(1000, screen.width().max(screen.height()));
+        assert!(thumbnail.width().max(thumbnail.height()) <= THUMBNAIL_SIZE);
+        assert!(exif::find_exif_thumbnail(&data).is_some());
+        // Turned like the photo itself
+        let rotated = orientation::to_color_image(thumbnail, Orientation::Rotate90);
+        assert!(rotated.size[0] < rotated.size[1]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_disk_cache_is_read_on_a_worker() {
+        use crate::app::models::ImageInfo;
+
+        let dir = TestDir::new("tiered_loader");
+        let photo_path = dir.join("1.jpg");
+        let data = std::fs::read("assets/samples/1.jpg").unwrap();
+        std::fs::write(&photo_path, &data).unwrap();
+        let disk_cache = Arc::new(DiskCache::default());
+        let photo = ImageInfo {
+            path_processed: photo_path.clone(),
+            fingerprint: Some("abc".to_string()),
+            ..Default::default()
+        };
+        disk_cache.open(&dir, &[photo]);
+        let thumbnail = scale_down(&data, Tier::Thumbnail, FALLBACK_SCREEN_SIZE).unwrap();
+        disk_cache.store(
+            &photo_path,
+            Tier::Thumbnail,
+            FALLBACK_SCREEN_SIZE,
+            &thumbnail,
+        );
+
+        let loader = TieredImageLoader::new(disk_cache, WorkerPool::spawn("test", 1));
+        let ctx = egui::Context::default();
+        let uri = tier_uri(photo_loader::photo_uri(&photo_path), Tier::Thumbnail);
+        let mut polls = 0;
+        let image = loop {
+            match loader.load(&ctx, &uri, SizeHint::default()).unwrap() {
+                ImagePoll::Ready { image } => break image,
+                ImagePoll::Pending { .. } => {
+                    polls += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+            }
+        };
+        // Never read on the calling thread
+        assert!(polls > 0);
+        assert_eq!(thumbnail.width() as usize, image.size[0]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_photos_are_decoded_on_a_worker() {
+        let disk_cache = Arc::new(DiskCache::default());
+        let loader = TieredImageLoader::new(disk_cache, WorkerPool::spawn("test", 1));
+        let ctx = egui::Context::default();
+        let uri = tier_uri("bytes://1.jpg".to_string(), Tier::Thumbnail);
+        ctx.include_bytes(uri.clone(), std::fs::read("assets/samples/1.jpg").unwrap());
+
+        let mut polls = 0;
+        let image = loop {
+            match loader.load(&ctx, &uri, SizeHint::default()).unwrap() {
+                ImagePoll::Ready { image } => break image,
+                ImagePoll::Pending { .. } => {
+                    polls += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+            }
+        };
+        // Never decoded on the calling thread
+        assert!(polls > 0);
+        assert!(image.size[0].max(image.size[1]) <= THUMBNAIL_SIZE as usize);
+    }
+}
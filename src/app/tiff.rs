@@ -0,0 +1,198 @@
+//! Minimal reader for the TIFF structure shared by EXIF blocks and most RAW containers.
+//! Only what we need to walk IFDs and pull values out of them, every read is bounds checked.
+
+pub const TAG_COMPRESSION: u16 = 0x0103;
+pub const TAG_STRIP_OFFSETS: u16 = 0x0111;
+pub const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+pub const TAG_SUB_IFDS: u16 = 0x014A;
+pub const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+pub const TAG_JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 0x0202;
+
+/// More IFDs than any real file has, protects us against offset loops in broken files.
+const MAX_IFDS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IfdEntry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    /// Where the value bytes start, either inside the entry or at the offset it points to.
+    value_offset: usize,
+}
+
+pub struct Ifd {
+    pub entries: Vec<IfdEntry>,
+    pub next_ifd_offset: Option<usize>,
+}
+
+impl Ifd {
+    pub fn get(&self, tag: u16) -> Option<&IfdEntry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+pub struct Tiff<'a> {
+    data: &'a [u8],
+    byte_order: ByteOrder,
+}
+
+impl<'a> Tiff<'a> {
+    /// Accepts the standard TIFF header as well as the Olympus (`IIRO`) and Panasonic (`IIU`)
+    /// variants, which only differ in the magic number.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let byte_order = match data.get(0..2)? {
+            b"II" => ByteOrder::Little,
+            b"MM" => ByteOrder::Big,
+            _ => return None,
+        };
+        let tiff = Self { data, byte_order };
+        match tiff.u16_at(2)? {
+            0x002A | 0x4F52 | 0x5352 | 0x0055 => Some(tiff),
+            _ => None,
+        }
+    }
+
+    pub fn first_ifd_offset(&self) -> Option<usize> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    pub fn read_ifd(&self, offset: usize) -> Option<Ifd> {
+        let entry_count = self.u16_at(offset)? as usize;
+        let mut entries = Vec::with_capacity(entry_count);
+        for index in 0..entry_count {
+            let entry_offset = offset + 2 + index * 12;
+            let tag = self.u16_at(entry_offset)?;
+            let field_type = self.u16_at(entry_offset + 2)?;
+            let count = self.u32_at(entry_offset + 4)?;
+            let value_size = type_size(field_type).saturating_mul(count as usize);
+            let value_offset = if value_size <= 4 {
+                entry_offset + 8
+            } else {
+                self.u32_at(entry_offset + 8)? as usize
+            };
+            entries.push(IfdEntry {
+                tag,
+                field_type,
+                count,
+                value_offset,
+            });
+        }
+        let next_ifd_offset = self
+            .u32_at(offset + 2 + entry_count * 12)
+            .filter(|next| *next != 0)
+            .map(|next| next as usize);
+        Some(Ifd {
+            entries,
+            next_ifd_offset,
+        })
+    }
+
+    /// Every IFD reachable from the header, following both the IFD chain and SubIFDs.
+    pub fn all_ifds(&self) -> Vec<Ifd> {
+        let mut ifds = Vec::new();
+        let mut visited = Vec::new();
+        let mut pending: Vec<usize> = self.first_ifd_offset().into_iter().collect();
+
+        while let Some(offset) = pending.pop() {
+            if visited.contains(&offset) || visited.len() >= MAX_IFDS {
+                continue;
+            }
+            visited.push(offset);
+            let Some(ifd) = self.read_ifd(offset) else {
+                continue;
+            };
+            if let Some(next) = ifd.next_ifd_offset {
+                pending.push(next);
+            }
+            if let Some(sub_ifds) = ifd.get(TAG_SUB_IFDS) {
+                pending.extend(self.entry_u32s(sub_ifds).into_iter().map(|o| o as usize));
+            }
+            ifds.push(ifd);
+        }
+        ifds
+    }
+
+    /// The first value of a SHORT or LONG entry.
+    pub fn entry_u32(&self, entry: &IfdEntry) -> Option<u32> {
+        self.entry_u32s(entry).first().copied()
+    }
+
+    /// All values of a SHORT, LONG or IFD entry.
+    pub fn entry_u32s(&self, entry: &IfdEntry) -> Vec<u32> {
+        (0..entry.count as usize)
+            .map_while(|index| match entry.field_type {
+                3 => self.u16_at(entry.value_offset + index * 2).map(u32::from),
+                4 | 13 => self.u32_at(entry.value_offset + index * 4),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All values of an unsigned RATIONAL entry as (numerator, denominator) pairs.
+    pub fn entry_rationals(&self, entry: &IfdEntry) -> Vec<(u32, u32)> {
+        if entry.field_type != 5 {
+            return Vec::new();
+        }
+        (0..entry.count as usize)
+            .map_while(|index| {
+                let offset = entry.value_offset + index * 8;
+                Some((self.u32_at(offset)?, self.u32_at(offset + 4)?))
+            })
+            .collect()
+    }
+
+    /// The first value of a RATIONAL entry as a float, `None` for a zero denominator.
+    pub fn entry_f64(&self, entry: &IfdEntry) -> Option<f64> {
+        let (numerator, denominator) = *self.entry_rationals(entry).first()?;
+        (denominator != 0).then(|| f64::from(numerator) / f64::from(denominator))
+    }
+
+    /// The raw value bytes of an entry.
+    pub fn entry_bytes(&self, entry: &IfdEntry) -> Option<&'a [u8]> {
+        let size = type_size(entry.field_type).checked_mul(entry.count as usize)?;
+        self.data
+            .get(entry.value_offset..entry.value_offset.checked_add(size)?)
+    }
+
+    /// An ASCII entry without its trailing NULs.
+    pub fn entry_ascii(&self, entry: &IfdEntry) -> Option<&'a str> {
+        if entry.field_type != 2 {
+            return None;
+        }
+        let bytes = self.entry_bytes(entry)?;
+        let text = std::str::from_utf8(bytes).ok()?;
+        Some(text.trim_end_matches('\0').trim())
+    }
+
+    pub fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match self.byte_order {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    pub fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.byte_order {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+fn type_size(field_type: u16) -> usize {
+    match field_type {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
@@ -0,0 +1,156 @@
+//! The freedesktop.org trash, so rejects can be restored from the desktop file manager instead
+//! of piling up in a chaffe folder. Every trashed file gets a `.trashinfo` with its original
+//! path, as described in https://specifications.freedesktop.org/trash-spec/latest/.
+
+use std::{
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use super::{commit_mode::move_file, percent_encoding};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Trash {
+    pub dir: PathBuf,
+}
+
+impl Trash {
+    /// The home trash, `$XDG_DATA_HOME/Trash` or `~/.local/share/Trash`. Files from other
+    /// volumes are copied there, which the spec allows.
+    pub fn home() -> Option<Self> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })?;
+        Some(Self {
+            dir: data_home.join("Trash"),
+        })
+    }
+
+    pub fn files_dir(&self) -> PathBuf {
+        self.dir.join("files")
+    }
+
+    /// Whether `path` is a file in this trash.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(self.files_dir())
+    }
+
+    fn info_path(&self, trashed: &Path) -> PathBuf {
+        let mut info_name = trashed.file_name().unwrap_or_default().to_owned();
+        info_name.push(".trashinfo");
+        self.dir.join("info").join(info_name)
+    }
+
+    /// A name is taken as long as either the file or its info exists.
+    pub fn is_taken(&self, trashed: &Path) -> bool {
+        fs::symlink_metadata(trashed).is_ok() || self.info_path(trashed).exists()
+    }
+
+    /// Moves `source` into the trash as `trashed`. The info file is created first, so a name
+    /// can't be claimed by another app in the meantime, and removed again when the move fails.
+    pub fn put(&self, source: &Path, trashed: &Path) -> io::Result<()> {
+        let info_path = self.info_path(trashed);
+        if let Some(info_dir) = info_path.parent() {
+            fs::create_dir_all(info_dir)?;
+        }
+        let original = std::path::absolute(source)?;
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)?
+            .write_all(trash_info(&original, SystemTime::now()).as_bytes())?;
+
+        let moved = trashed
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| move_file(source, trashed));
+        if moved.is_err() {
+            let _ = fs::remove_file(&info_path);
+        }
+        moved
+    }
+
+    /// Moves a trashed file back to `original` and forgets its info.
+    pub fn restore(&self, trashed: &Path, original: &Path) -> io::Result<()> {
+        move_file(trashed, original)?;
+        let _ = fs::remove_file(self.info_path(trashed));
+        Ok(())
+    }
+}
+
+fn trash_info(original: &Path, deleted_at: SystemTime) -> String {
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        // Paths are stored URL escaped
+        percent_encoding::encode_path(original),
+        deletion_date(deleted_at)
+    )
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in local time.
+#[cfg(unix)]
+fn deletion_date(deleted_at: SystemTime) -> String {
+    let seconds = deleted_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs()) as libc::time_t;
+    let mut local = std::mem::MaybeUninit::<libc::tm>::uninit();
+    // SAFETY: `local` is only read after localtime_r filled it in
+    let local = unsafe {
+        if libc::localtime_r(&seconds, local.as_mut_ptr()).is_null() {
+            return String::new();
+        }
+        local.assume_init()
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        local.tm_year + 1900,
+        local.tm_mon + 1,
+        local.tm_mday,
+        local.tm_hour,
+        local.tm_min,
+        local.tm_sec
+    )
+}
+
+/// Without a freedesktop trash to put files in the date doesn't matter, it's left empty.
+#[cfg(not(unix))]
+fn deletion_date(_deleted_at: SystemTime) -> String {
+    String::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+
+    #[test]
+    fn test_put_and_restore() {
+        let dir = TestDir::new("trash");
+        fs::write(dir.join("1.jpg"), b"jpg").unwrap();
+        let trash = Trash {
+            dir: dir.join("Trash"),
+        };
+        let trashed = trash.files_dir().join("1.jpg");
+
+        trash.put(&dir.join("1.jpg"), &trashed).unwrap();
+        assert!(!dir.join("1.jpg").exists());
+        assert!(trash.is_taken(&trashed));
+        let info = fs::read_to_string(dir.join("Trash/info/1.jpg.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"));
+        assert!(info.contains("blitz_test_trash/1.jpg\nDeletionDate="));
+
+        // A second file can't claim the name while the info is there
+        fs::write(dir.join("1.jpg"), b"other jpg").unwrap();
+        assert!(trash.put(&dir.join("1.jpg"), &trashed).is_err());
+        fs::remove_file(dir.join("1.jpg")).unwrap();
+
+        trash.restore(&trashed, &dir.join("1.jpg")).unwrap();
+        assert_eq!(b"jpg".to_vec(), fs::read(dir.join("1.jpg")).unwrap());
+        assert!(!trash.is_taken(&trashed));
+    }
+}
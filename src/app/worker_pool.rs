@@ -0,0 +1,69 @@
+//! A fixed number of threads for the loaders and the prefetcher, so scrolling through a large
+//! folder queues up the reads instead of starting a thread for every photo.
+
+use std::{
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Reading a photo mostly waits for the disk, a few reads at once keep it busy.
+pub const LOAD_WORKERS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Cloning shares the workers.
+#[derive(Clone)]
+pub struct WorkerPool {
+    jobs: Sender<Job>,
+}
+
+impl WorkerPool {
+    /// Starts `count` workers named after `name`, they stop once every clone of the pool is
+    /// dropped.
+    pub fn spawn(name: &str, count: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker in 0..count {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("{} {}", name, worker))
+                .spawn(move || loop {
+                    // The receiver is only locked while waiting, not while working
+                    let Ok(job) = receiver.lock().unwrap().recv() else {
+                        return;
+                    };
+                    job();
+                })
+                .expect("couldn't start a worker thread");
+        }
+        Self { jobs }
+    }
+
+    /// Runs `job` on the next free worker, in the order the jobs came in.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        let _ = self.jobs.send(Box::new(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs_run_on_the_workers() {
+        let pool = WorkerPool::spawn("test", 2);
+        let (results, received) = mpsc::channel();
+        for job in 0..10 {
+            let results = results.clone();
+            pool.execute(move || results.send(job).unwrap());
+        }
+        drop(results);
+
+        let mut done: Vec<i32> = received.iter().collect();
+        done.sort();
+        assert_eq!((0..10).collect::<Vec<_>>(), done);
+    }
+}
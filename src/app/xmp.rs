@@ -0,0 +1,394 @@
+//! XMP sidecars, so ratings and labels made in blitz show up in darktable and friends and
+//! the other way round. Existing sidecars are edited in place, everything blitz doesn't know
+//! about is left alone.
+
+// The web version only plans commits, there are no sidecars to read or write
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use super::{
+    exif::{find_embedded_xmp, ImageMetadata},
+    models::{ColorLabel, ImageInfo, Rating, MAX_STARS},
+    storage::write_atomically,
+};
+
+const RATING: &str = "xmp:Rating";
+const LABEL: &str = "xmp:Label";
+const DESCRIPTION: &str = "<rdf:Description";
+/// Keywords blitz writes are below this one, e.g. `blitz|keep`.
+const KEYWORD_PREFIX: &str = "blitz|";
+
+const EMPTY_SIDECAR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+/// What blitz keeps in a sidecar. Rejects are rated −1 the way darktable does it, keepers
+/// get their stars but at least one, so they don't read back as unrated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SidecarRating {
+    pub rating: i8,
+    pub label: Option<ColorLabel>,
+}
+
+impl SidecarRating {
+    pub fn of(photo: &ImageInfo) -> Self {
+        let rating = match photo.rating {
+            Rating::Unrated => 0,
+            Rating::Approve => photo.stars.max(1) as i8,
+            Rating::Remove => -1,
+        };
+        Self {
+            rating,
+            label: photo.label,
+        }
+    }
+
+    /// The verdict and stars this stands for.
+    pub fn verdict(self) -> (Rating, u8) {
+        match self.rating {
+            rating if rating < 0 => (Rating::Remove, 0),
+            0 => (Rating::Unrated, 0),
+            stars => (Rating::Approve, (stars as u8).min(MAX_STARS)),
+        }
+    }
+
+    fn keyword(self) -> Option<&'static str> {
+        match self.verdict().0 {
+            Rating::Unrated => None,
+            Rating::Approve => Some("blitz|keep"),
+            Rating::Remove => Some("blitz|reject"),
+        }
+    }
+}
+
+/// `IMG_0001.JPG.xmp` as darktable names them and `IMG_0001.xmp` as most other tools do.
+fn sidecar_candidates(file: &Path) -> [PathBuf; 2] {
+    let mut full_name = file.as_os_str().to_owned();
+    full_name.push(".xmp");
+    [PathBuf::from(full_name), file.with_extension("xmp")]
+}
+
+/// The sidecars next to `file`, usually none or one.
+pub fn existing_sidecars(file: &Path) -> Vec<PathBuf> {
+    sidecar_candidates(file)
+        .into_iter()
+        .filter(|sidecar| sidecar.is_file())
+        .collect()
+}
+
+/// The sidecars `file` has once its rating is written, if `written`, the one `write_sidecar`
+/// creates when there is none yet.
+pub fn planned_sidecars(file: &Path, written: bool) -> Vec<PathBuf> {
+    match existing_sidecars(file) {
+        sidecars if sidecars.is_empty() && written => vec![sidecar_candidates(file)[0].clone()],
+        sidecars => sidecars,
+    }
+}
+
+/// Where `sidecar` of `source` goes when the file is committed to `destination`, named the
+/// same way it was before.
+pub fn sidecar_destination(sidecar: &Path, source: &Path, destination: &Path) -> PathBuf {
+    let [full_name, _] = sidecar_candidates(source);
+    let [destination_full_name, destination_stem] = sidecar_candidates(destination);
+    match sidecar == full_name {
+        true => destination_full_name,
+        false => destination_stem,
+    }
+}
+
+/// Reads the rating of the first sidecar of `file`, along with when it was last changed.
+pub fn read_sidecar(file: &Path) -> Option<(SidecarRating, SystemTime)> {
+    let sidecar = existing_sidecars(file).into_iter().next()?;
+    let xml = std::fs::read_to_string(&sidecar).ok()?;
+    let rating = read_xmp(&xml)?;
+    let modified = std::fs::metadata(&sidecar)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    Some((rating, modified))
+}
+
+/// The rating and label of an XMP packet, be it a sidecar or embedded in the photo.
+pub fn read_xmp(xml: &str) -> Option<SidecarRating> {
+    // Some tools write ratings as decimals, e.g. `3.0`
+    let rating = property(xml, RATING)
+        .and_then(|rating| rating.trim().parse::<f32>().ok())
+        .map(|rating| rating.round().clamp(-1.0, MAX_STARS as f32) as i8);
+    let label = property(xml, LABEL).and_then(|name| {
+        ColorLabel::ALL
+            .into_iter()
+            .find(|label| label.name().eq_ignore_ascii_case(name.trim()))
+    });
+    if rating.is_none() && label.is_none() {
+        return None;
+    }
+    Some(SidecarRating {
+        rating: rating.unwrap_or(0),
+        label,
+    })
+}
+
+/// What the camera or an editor recorded inside the photo itself, an XMP packet or else the
+/// EXIF rating. Unrated photos have none.
+pub fn embedded_rating(data: &[u8], metadata: &ImageMetadata) -> Option<SidecarRating> {
+    find_embedded_xmp(data)
+        .and_then(read_xmp)
+        .or(metadata.rating.map(|rating| SidecarRating {
+            rating,
+            label: None,
+        }))
+        .filter(|rating| *rating != SidecarRating::default())
+}
+
+/// Updates the sidecar of `file`, creating one named the darktable way if there is none.
+pub fn write_sidecar(file: &Path, rating: SidecarRating) -> io::Result<()> {
+    let sidecar = existing_sidecars(file)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| sidecar_candidates(file)[0].clone());
+    let xml = match std::fs::read_to_string(&sidecar) {
+        Ok(xml) if xml.contains(DESCRIPTION) => xml,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the sidecar has no rdf:Description",
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => EMPTY_SIDECAR.to_string(),
+        Err(err) => return Err(err),
+    };
+    write_atomically(&sidecar, update_xmp(&xml, rating).as_bytes())
+}
+
+/// Writes the sidecars of the processed file and its raw companion.
+pub fn write_photo_sidecars(photo: &ImageInfo) -> io::Result<()> {
+    let rating = SidecarRating::of(photo);
+    for file in [Some(&photo.path_processed), photo.path_raw.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        write_sidecar(file, rating)?;
+    }
+    Ok(())
+}
+
+fn update_xmp(xml: &str, rating: SidecarRating) -> String {
+    let mut xml = ensure_namespace(xml, "xmp", "http://ns.adobe.com/xap/1.0/");
+    xml = ensure_namespace(&xml, "dc", "http://purl.org/dc/elements/1.1/");
+    xml = set_property(&xml, RATING, Some(&rating.rating.to_string()));
+    xml = set_property(&xml, LABEL, rating.label.map(|label| label.name()));
+    set_keyword(&xml, rating.keyword())
+}
+
+/// The value of a property written either as an attribute or as an element.
+fn property<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    attribute_value_range(xml, name)
+        .or_else(|| element_value_range(xml, name))
+        .map(|range| &xml[range])
+}
+
+fn attribute_value_range(xml: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    let pattern = format!("{}=\"", name);
+    let (name_start, _) = xml.match_indices(&pattern).find(|(index, _)| {
+        xml[..*index]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_whitespace())
+    })?;
+    let start = name_start + pattern.len();
+    let end = start + xml[start..].find('"')?;
+    Some(start..end)
+}
+
+fn element_value_range(xml: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(start..end)
+}
+
+/// Replaces or removes a property, new ones become attributes of `rdf:Description`.
+fn set_property(xml: &str, name: &str, value: Option<&str>) -> String {
+    let mut xml = xml.to_string();
+    if let Some(range) = attribute_value_range(&xml, name) {
+        match value {
+            Some(value) => xml.replace_range(range, value),
+            // Takes the whitespace before it, `name="` and the closing quote along
+            None => xml.replace_range(range.start - name.len() - 3..range.end + 1, ""),
+        }
+        return xml;
+    }
+    if let Some(range) = element_value_range(&xml, name) {
+        match value {
+            Some(value) => xml.replace_range(range, value),
+            None => xml.replace_range(range.start - name.len() - 2..range.end + name.len() + 3, ""),
+        }
+        return xml;
+    }
+    if let (Some(value), Some(tag_end)) = (value, description_tag_end(&xml)) {
+        xml.insert_str(tag_end, &format!(" {}=\"{}\"", name, value));
+    }
+    xml
+}
+
+fn ensure_namespace(xml: &str, prefix: &str, uri: &str) -> String {
+    let mut xml = xml.to_string();
+    if !xml.contains(&format!("xmlns:{}=", prefix)) {
+        if let Some(tag_end) = description_tag_end(&xml) {
+            xml.insert_str(tag_end, &format!(" xmlns:{}=\"{}\"", prefix, uri));
+        }
+    }
+    xml
+}
+
+/// Where attributes can be appended to the `rdf:Description` start tag, before its `>` or
+/// `/>`.
+fn description_tag_end(xml: &str) -> Option<usize> {
+    let start = xml.find(DESCRIPTION)?;
+    let end = start + xml[start..].find('>')?;
+    Some(match xml[..end].ends_with('/') {
+        true => end - 1,
+        false => end,
+    })
+}
+
+/// Swaps the blitz keyword in `dc:subject`, keywords from other tools stay.
+fn set_keyword(xml: &str, keyword: Option<&str>) -> String {
+    let mut xml = xml.to_string();
+    let item_start = format!("<rdf:li>{}", KEYWORD_PREFIX);
+    while let Some(start) = xml.find(&item_start) {
+        let Some(length) = xml[start..].find("</rdf:li>") else {
+            break;
+        };
+        xml.replace_range(start..start + length + "</rdf:li>".len(), "");
+    }
+    let Some(keyword) = keyword else {
+        return xml;
+    };
+
+    let item = format!("<rdf:li>{}</rdf:li>", keyword);
+    if let Some(bag_start) = xml
+        .find("<dc:subject>")
+        .and_then(|subject| xml[subject..].find("<rdf:Bag>").map(|bag| subject + bag))
+    {
+        xml.insert_str(bag_start + "<rdf:Bag>".len(), &item);
+        return xml;
+    }
+
+    let subject = format!("<dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>", item);
+    let Some(tag_end) = description_tag_end(&xml) else {
+        return xml;
+    };
+    if xml[tag_end..].starts_with("/>") {
+        xml.replace_range(
+            tag_end..tag_end + 2,
+            &format!(">{}</rdf:Description>", subject),
+        );
+    } else {
+        xml.insert_str(tag_end + 1, &subject);
+    }
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_dir::TestDir;
+    use std::fs;
+
+    const DARKTABLE_SIDECAR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:darktable="http://darktable.sf.net/"
+    xmp:Rating="1"
+    darktable:xmp_version="5">
+   <darktable:history>
+    <rdf:Seq/>
+   </darktable:history>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    #[test]
+    fn test_update_keeps_foreign_data() {
+        let keeper = SidecarRating {
+            rating: 4,
+            label: Some(ColorLabel::Green),
+        };
+        let xml = update_xmp(DARKTABLE_SIDECAR, keeper);
+        assert_eq!(Some("4"), property(&xml, RATING));
+        assert_eq!(Some("Green"), property(&xml, LABEL));
+        assert!(xml.contains("<rdf:li>blitz|keep</rdf:li>"));
+        assert!(xml.contains("darktable:xmp_version=\"5\""));
+        assert!(xml.contains("<darktable:history>"));
+
+        let reject = SidecarRating {
+            rating: -1,
+            label: None,
+        };
+        let xml = update_xmp(&xml, reject);
+        assert_eq!(Some("-1"), property(&xml, RATING));
+        assert_eq!(None, property(&xml, LABEL));
+        assert!(!xml.contains("blitz|keep"));
+        assert_eq!(1, xml.matches("<rdf:li>blitz|reject</rdf:li>").count());
+    }
+
+    #[test]
+    fn test_element_properties_and_self_closing_description() {
+        let xml = r#"<rdf:RDF><rdf:Description rdf:about=""><xmp:Rating>2</xmp:Rating></rdf:Description></rdf:RDF>"#;
+        assert_eq!(Some("2"), property(xml, RATING));
+        let xml = set_property(xml, RATING, Some("5"));
+        assert_eq!(Some("5"), property(&xml, RATING));
+
+        let xml = r#"<rdf:RDF><rdf:Description rdf:about="" xmp:Rating="0"/></rdf:RDF>"#;
+        let xml = set_keyword(xml, Some("blitz|keep"));
+        assert!(xml.contains(
+            r#"xmp:Rating="0"><dc:subject><rdf:Bag><rdf:li>blitz|keep</rdf:li></rdf:Bag></dc:subject></rdf:Description>"#
+        ));
+    }
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let dir = TestDir::new("xmp");
+        let photo = dir.join("IMG_0001.JPG");
+        let raw = dir.join("IMG_0002.CR2");
+        fs::write(dir.join("IMG_0002.xmp"), DARKTABLE_SIDECAR).unwrap();
+
+        let rating = SidecarRating {
+            rating: 3,
+            label: Some(ColorLabel::Red),
+        };
+        write_sidecar(&photo, rating).unwrap();
+        write_sidecar(&raw, rating).unwrap();
+
+        assert_eq!(
+            vec![dir.join("IMG_0001.JPG.xmp")],
+            existing_sidecars(&photo)
+        );
+        assert_eq!(vec![dir.join("IMG_0002.xmp")], existing_sidecars(&raw));
+        assert_eq!(rating, read_sidecar(&photo).unwrap().0);
+        assert_eq!(rating, read_sidecar(&raw).unwrap().0);
+        assert_eq!((Rating::Approve, 3), rating.verdict());
+        assert_eq!(
+            dir.join("wheat/IMG_0002_1.xmp"),
+            sidecar_destination(
+                &dir.join("IMG_0002.xmp"),
+                &raw,
+                &dir.join("wheat/IMG_0002_1.CR2")
+            )
+        );
+    }
+}